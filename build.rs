@@ -1,7 +1,10 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::configure()
         .out_dir("src/proto")
-        .compile(&["proto/internalfiles.proto"], &["."])
+        .compile(
+            &["proto/internalfiles.proto", "proto/records.proto"],
+            &["."],
+        )
         .expect("failed to compile protos");
     Ok(())
 }
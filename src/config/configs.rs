@@ -2,15 +2,87 @@ use std::{collections::HashSet, error::Error, fs};
 
 use serde_derive::Deserialize;
 
+use crate::util::compress::Codec;
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    #[default]
+    Rocksdb,
+    Fs,
+    Memory,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CompactionStyle {
+    Level,
+    Universal,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RocksDbCompression {
+    None,
+    Snappy,
+    Zlib,
+    Bz2,
+    Lz4,
+    Lz4hc,
+    Zstd,
+}
+
+// 未设置的项使用 RocksDB 默认值，大小单位均为字节；
+// 拒绝未知的项，避免 [database] 的配置写在该表之后时被静默忽略
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RocksDb {
+    pub block_cache_size: Option<usize>,
+    pub write_buffer_size: Option<usize>,
+    pub max_open_files: Option<i32>,
+    pub compaction_style: Option<CompactionStyle>,
+    pub compression: Option<RocksDbCompression>,
+    // 开启 BlobDB 后内容列族中大于 min_blob_size 的值单独存放在 blob 文件中
+    pub enable_blob_files: bool,
+    pub min_blob_size: Option<u64>,
+    pub blob_file_size: Option<u64>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Database {
     pub url: String,
+    #[serde(default)]
+    pub engine: Engine,
+    // 仅 memory 引擎使用，单位字节，不设置则不限制
+    pub memory_limit: Option<usize>,
+    #[serde(default)]
+    pub rocksdb: RocksDb,
+    #[serde(default = "default_chunk_size")]
+    pub chunk_size: usize,
+    // 读取时重新计算 SHA256 并与文件 hash 比对
+    #[serde(default = "default_verify_on_read")]
+    pub verify_on_read: bool,
+}
+
+fn default_chunk_size() -> usize {
+    4 * 1024 * 1024
+}
+
+fn default_verify_on_read() -> bool {
+    true
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Server {
     pub address: String,
     pub grpc_address: String,
+    // 按 hash 下载的响应允许客户端和 CDN 缓存的时间（秒）
+    #[serde(default = "default_cache_max_age")]
+    pub cache_max_age: u64,
+}
+
+fn default_cache_max_age() -> u64 {
+    365 * 24 * 60 * 60
 }
 
 #[derive(Debug, Deserialize)]
@@ -19,11 +91,200 @@ pub struct Cluster {
     pub min_count: usize,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Compression {
+    pub codec: Codec,
+    // 小于该大小（字节）的文件不压缩
+    pub threshold: usize,
+    pub level: i32,
+    // 首个分块压缩后与原大小之比高于该值时视为不可压缩，按原样保存
+    pub max_ratio: f64,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression {
+            codec: Codec::None,
+            threshold: 4096,
+            level: 3,
+            max_ratio: 0.9,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct Encryption {
+    pub enabled: bool,
+    // 主密钥为 64 位十六进制字符串，优先从环境变量读取，其次从文件读取
+    pub key_env: Option<String>,
+    pub key_file: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Scrub {
+    pub enabled: bool,
+    // 每秒校验的文件数
+    pub rate: f64,
+    // 两轮巡检之间的间隔（秒）
+    pub interval_secs: u64,
+}
+
+impl Default for Scrub {
+    fn default() -> Self {
+        Scrub {
+            enabled: false,
+            rate: 10.0,
+            interval_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Backup {
+    // 快照保存目录，每个快照为其中以创建时间命名的子目录
+    pub dir: String,
+}
+
+impl Default for Backup {
+    fn default() -> Self {
+        Backup {
+            dir: String::from("./checkpoints"),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Capacity {
+    // 已用空间超过总容量的该比例后拒绝上传
+    pub high_watermark: f64,
+}
+
+impl Default for Capacity {
+    fn default() -> Self {
+        Capacity {
+            high_watermark: 0.9,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Expire {
+    pub enabled: bool,
+    // 两次清理过期文件之间的间隔（秒）
+    pub interval_secs: u64,
+}
+
+impl Default for Expire {
+    fn default() -> Self {
+        Expire {
+            enabled: true,
+            interval_secs: 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Tier {
+    pub enabled: bool,
+    // 冷存储目录，通常位于 HDD 等廉价磁盘
+    pub cold_url: String,
+    // 超过该时间（秒）未被读取的文件移入冷存储，冷存储中的文件被读取后移回
+    pub cold_after_secs: u64,
+    // 两轮迁移之间的间隔（秒）
+    pub interval_secs: u64,
+}
+
+impl Default for Tier {
+    fn default() -> Self {
+        Tier {
+            enabled: false,
+            cold_url: String::from("./cold"),
+            cold_after_secs: 30 * 24 * 60 * 60,
+            interval_secs: 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Cache {
+    // 读缓存的最大字节数，为 0 时不缓存
+    pub size: usize,
+    // 超过该大小的文件不缓存
+    pub max_object_size: usize,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache {
+            size: 64 * 1024 * 1024,
+            max_object_size: 8 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Upload {
+    // 单个文件的最大字节数，上传时边接收边检查
+    pub max_file_size: u64,
+}
+
+impl Default for Upload {
+    fn default() -> Self {
+        Upload {
+            max_file_size: 4 * 1024 * 1024 * 1024,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Tus {
+    // 可续传上传超过该时间（秒）未继续上传时删除
+    pub expire_secs: u64,
+}
+
+impl Default for Tus {
+    fn default() -> Self {
+        Tus {
+            expire_secs: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Configs {
     pub database: Database,
     pub server: Server,
     pub cluster: Cluster,
+    #[serde(default)]
+    pub compression: Compression,
+    #[serde(default)]
+    pub encryption: Encryption,
+    #[serde(default)]
+    pub scrub: Scrub,
+    #[serde(default)]
+    pub backup: Backup,
+    #[serde(default)]
+    pub capacity: Capacity,
+    #[serde(default)]
+    pub expire: Expire,
+    #[serde(default)]
+    pub tier: Tier,
+    #[serde(default)]
+    pub cache: Cache,
+    #[serde(default)]
+    pub upload: Upload,
+    #[serde(default)]
+    pub tus: Tus,
 }
 
 impl Configs {
@@ -0,0 +1,22 @@
+use crate::model::file_model::{CacheHead, CapacityHead, CheckpointHead};
+use crate::service::{backup_service, capacity_service, file_service};
+use anyhow::Result;
+use poem::{handler, web::Json};
+
+#[handler]
+pub async fn checkpoint() -> Result<Json<CheckpointHead>> {
+    let path = backup_service::checkpoint().await?;
+    Ok(Json(CheckpointHead::new(true, path.display().to_string())))
+}
+
+#[handler]
+pub async fn capacity() -> Result<Json<CapacityHead>> {
+    let capacity = capacity_service::capacity().await?;
+    let full = capacity.is_some_and(|c| capacity_service::is_full(&c, 0));
+    Ok(Json(CapacityHead::new(full, capacity)))
+}
+
+#[handler]
+pub fn cache() -> Json<CacheHead> {
+    Json(file_service::cache_stats())
+}
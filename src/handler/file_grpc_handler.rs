@@ -1,9 +1,17 @@
-use crate::service::file_service;
+use crate::model::file_model::FileInfo;
+use crate::service::backup_service;
+use crate::service::capacity_service::{self, InsufficientStorageError};
+use crate::service::file_service::{self, CorruptedError, FileTooLargeError, StagedFile, Upload};
+use crate::util::crypto;
+use anyhow::anyhow;
 use internal_files::{
-    internal_files_server::InternalFiles, DownloadRequest, DownloadResponse, ExistsRequest,
-    ExistsResponse, FileHead, UploadRequest, UploadResponse,
+    internal_files_server::InternalFiles, CapacityRequest, CapacityResponse, CheckpointRequest,
+    CheckpointResponse, DeleteRequest, DeleteResponse, DownloadRequest, DownloadResponse,
+    ExistsRequest, ExistsResponse, FileHead, StatRequest, StatResponse, UploadChunk, UploadRequest,
+    UploadResponse,
 };
-use tonic::{Code, Request, Response, Status};
+use tokio::task;
+use tonic::{Code, Request, Response, Status, Streaming};
 use tracing::info;
 
 pub mod internal_files {
@@ -22,17 +30,42 @@ impl InternalFiles for InternalFilesService {
         info!("receive upload request");
         let req = request.into_inner();
 
+        let size = req.files.iter().map(|file| file.content.len() as u64).sum();
+        if let Err(err) = capacity_service::check(size).await {
+            let code = match err.is::<InsufficientStorageError>() {
+                true => Code::ResourceExhausted,
+                false => Code::Internal,
+            };
+            return Err(Status::new(code, format!("{}", err)));
+        }
+
         let mut files: Vec<FileHead> = Vec::new();
         for file in req.files {
-            let ret = file_service::save(file.hash.as_str(), file.content.clone()).await;
-            let success = match ret {
-                Ok(_) => true,
-                Err(_) => false,
+            let mut file_info = FileInfo::new(
+                file.hash.clone(),
+                file.name.clone(),
+                file.size as usize,
+                file.content,
+            );
+            file_info.expires_at = (file.expires_at > 0).then_some(file.expires_at);
+            // 内容与发送方给出的 hash 不一致时不保存
+            let (file_info, digest) = task::spawn_blocking(move || {
+                let digest = crypto::sha256_digest(&file_info.content);
+                (file_info, digest)
+            })
+            .await
+            .map_err(|err| Status::new(Code::Internal, format!("{:?}", err)))?;
+            let success = match digest == file.hash {
+                true => file_service::save(&file_info).await.is_ok(),
+                false => {
+                    info!("file {} digest is {}", file.hash, digest);
+                    false
+                }
             };
             files.push(FileHead {
-                success: success,
-                hash: file.hash.clone(),
-                name: file.name.clone(),
+                success,
+                hash: file.hash,
+                name: file.name,
                 size: file.size,
             });
         }
@@ -40,6 +73,18 @@ impl InternalFiles for InternalFilesService {
         Ok(Response::new(UploadResponse { files }))
     }
 
+    async fn upload_stream(
+        &self,
+        request: Request<Streaming<UploadChunk>>,
+    ) -> Result<Response<UploadResponse>, Status> {
+        info!("receive upload stream request");
+        // 在单独的任务中接收，发送方中途断开时也能删除已写入的分块
+        match task::spawn(receive_stream(request.into_inner())).await {
+            Ok(result) => result,
+            Err(err) => Err(Status::new(Code::Internal, format!("{:?}", err))),
+        }
+    }
+
     async fn exists(
         &self,
         request: Request<ExistsRequest>,
@@ -62,13 +107,184 @@ impl InternalFiles for InternalFilesService {
         info!("receive download request: {:?}", request);
         let req = request.into_inner();
 
-        let file = file_service::find(req.hash.as_str()).await;
+        let file = if req.hash.is_empty() {
+            file_service::find_by_name(req.name.as_str()).await
+        } else {
+            file_service::find(req.hash.as_str()).await
+        };
         match file {
             Ok(f) => Ok(Response::new(DownloadResponse {
                 name: f.file_name,
                 content: f.content,
+                expires_at: f.expires_at.unwrap_or_default(),
+                hash: f.file_hash,
+            })),
+            Err(err) if err.is::<CorruptedError>() => {
+                Err(Status::new(Code::DataLoss, format!("{}", err)))
+            }
+            Err(err) => Err(Status::new(Code::Internal, format!("{:?}", err))),
+        }
+    }
+
+    async fn stat(&self, request: Request<StatRequest>) -> Result<Response<StatResponse>, Status> {
+        info!("receive stat request: {:?}", request);
+        let req = request.into_inner();
+
+        match file_service::stat(req.hash.as_str()).await {
+            Ok(None) => Err(Status::new(
+                Code::NotFound,
+                format!("file {} not found", req.hash),
+            )),
+            Ok(Some(stat)) => Ok(Response::new(StatResponse {
+                hash: stat.file_hash,
+                name: stat.file_name,
+                size: stat.size as u64,
+                created_at: stat.created_at,
+                expires_at: stat.expires_at.unwrap_or_default(),
+            })),
+            Err(err) => Err(Status::new(Code::Internal, format!("{:?}", err))),
+        }
+    }
+
+    async fn delete(
+        &self,
+        request: Request<DeleteRequest>,
+    ) -> Result<Response<DeleteResponse>, Status> {
+        info!("receive delete request: {:?}", request);
+        let req = request.into_inner();
+
+        let deleted = if req.hash.is_empty() {
+            file_service::delete_name(req.name.as_str()).await
+        } else {
+            file_service::delete(req.hash.as_str()).await
+        };
+        match deleted {
+            Ok(deleted) => Ok(Response::new(DeleteResponse { deleted })),
+            Err(err) => Err(Status::new(Code::Internal, format!("{:?}", err))),
+        }
+    }
+
+    async fn checkpoint(
+        &self,
+        request: Request<CheckpointRequest>,
+    ) -> Result<Response<CheckpointResponse>, Status> {
+        info!("receive checkpoint request: {:?}", request);
+
+        match backup_service::checkpoint().await {
+            Ok(path) => Ok(Response::new(CheckpointResponse {
+                path: path.display().to_string(),
             })),
             Err(err) => Err(Status::new(Code::Internal, format!("{:?}", err))),
         }
     }
+
+    async fn capacity(
+        &self,
+        request: Request<CapacityRequest>,
+    ) -> Result<Response<CapacityResponse>, Status> {
+        info!("receive capacity request: {:?}", request);
+        let req = request.into_inner();
+
+        match capacity_service::capacity().await {
+            Ok(Some(capacity)) => Ok(Response::new(CapacityResponse {
+                total: capacity.total,
+                used: capacity.used,
+                available: capacity.available,
+                full: capacity_service::is_full(&capacity, req.size),
+            })),
+            Ok(None) => Ok(Response::new(CapacityResponse::default())),
+            Err(err) => Err(Status::new(Code::Internal, format!("{:?}", err))),
+        }
+    }
+}
+
+async fn receive_stream(
+    mut stream: Streaming<UploadChunk>,
+) -> Result<Response<UploadResponse>, Status> {
+    let mut current: Option<Upload> = None;
+    let mut staged: Vec<StagedFile> = Vec::new();
+    let mut files: Vec<FileHead> = Vec::new();
+    let mut atomic = false;
+    let mut end = false;
+    let result: anyhow::Result<()> = async {
+        while let Some(chunk) = stream.message().await? {
+            if chunk.start {
+                if current.is_some() {
+                    return Err(anyhow!("file {} is not finished", chunk.name));
+                }
+                atomic = chunk.atomic;
+                let expires_at = (chunk.expires_at > 0).then_some(chunk.expires_at);
+                current = Some(Upload::new(&chunk.name, expires_at).await?);
+            }
+            if !chunk.content.is_empty() {
+                let upload = current
+                    .as_mut()
+                    .ok_or_else(|| anyhow!("upload is not started"))?;
+                upload.write(&chunk.content).await?;
+            }
+            if chunk.finish {
+                let upload = current
+                    .take()
+                    .ok_or_else(|| anyhow!("upload is not started"))?;
+                let file = upload.finish().await?;
+                // 内容与发送方计算的 hash 不一致时不保存
+                if file.hash() != chunk.hash {
+                    info!("file {} digest is {}", chunk.hash, file.hash());
+                    files.push(file_head(&file, false));
+                    file.discard().await;
+                } else if atomic {
+                    staged.push(file);
+                } else {
+                    let success = file_service::save_staged(&file).await.is_ok();
+                    files.push(file_head(&file, success));
+                }
+            }
+            if chunk.end {
+                end = true;
+                break;
+            }
+        }
+        Ok(())
+    }
+    .await;
+
+    if let Some(upload) = current.take() {
+        upload.abort().await;
+    }
+    if result.is_err() || !end {
+        for file in staged {
+            file.discard().await;
+        }
+        return match result {
+            Err(err) if err.is::<FileTooLargeError>() => {
+                Err(Status::new(Code::InvalidArgument, format!("{}", err)))
+            }
+            Err(err) if err.is::<InsufficientStorageError>() => {
+                Err(Status::new(Code::ResourceExhausted, format!("{}", err)))
+            }
+            Err(err) => Err(Status::new(Code::Internal, format!("{:?}", err))),
+            _ => Err(Status::new(Code::Aborted, "upload is interrupted")),
+        };
+    }
+
+    if atomic {
+        let success = match file_service::save_staged_all(&staged).await {
+            Ok(()) => true,
+            Err(err) => {
+                info!("atomic upload failed: {:?}", err);
+                false
+            }
+        };
+        files.extend(staged.iter().map(|file| file_head(file, success)));
+    }
+    Ok(Response::new(UploadResponse { files }))
+}
+
+fn file_head(file: &StagedFile, success: bool) -> FileHead {
+    FileHead {
+        success,
+        hash: file.hash().to_string(),
+        name: file.name().to_string(),
+        size: file.size() as i64,
+    }
 }
@@ -1,65 +1,206 @@
 use crate::config;
 use crate::handler::file_grpc_handler::internal_files::{
-    internal_files_client::InternalFilesClient, DownloadRequest, ExistsRequest, InternalFile,
-    UploadRequest,
+    internal_files_client::InternalFilesClient, CapacityRequest, DeleteRequest, DownloadRequest,
+    DownloadResponse, ExistsRequest, StatRequest, UploadChunk, UploadResponse,
 };
-use crate::model::file_model::{FileHead, FileInfo};
-use crate::service::file_service;
-use crate::util::crypto;
-use anyhow::Result;
+use crate::model::file_model::{now_secs, BatchHead, DeleteHead, FileHead, FileList, FileStat};
+use crate::service::capacity_service::{self, InsufficientStorageError};
+use crate::service::file_service::{
+    self, FileContent, FileReader, FileTooLargeError, StagedFile, Upload,
+};
+use crate::util::range::{self, ByteRange, Ranges};
+use anyhow::{anyhow, Result};
+use data_encoding::HEXLOWER;
 use poem::{
     handler,
-    http::StatusCode,
-    web::{Json, Multipart, Path},
-    Response,
+    http::{header, HeaderMap, StatusCode},
+    web::{
+        headers::{ETag, HeaderMapExt, IfNoneMatch, LastModified},
+        Field, Json, Multipart, Path, Query,
+    },
+    Body, IntoResponse, Response,
 };
 use rand::seq::SliceRandom;
-use tokio::task::JoinSet;
+use serde::Deserialize;
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::mpsc;
+use tokio::task::{self, JoinSet};
+use tokio_stream::wrappers::ReceiverStream;
 use tracing::info;
 
+// 每个副本节点最多缓存的待发送消息数
+const REPLICA_BUFFER: usize = 4;
+
+// 流式下载时最多预读的分块数
+const DOWNLOAD_BUFFER: usize = 2;
+
+// 文件名可能指向新的内容，客户端每次使用缓存前都需按 ETag 重新验证
+const REVALIDATE: &str = "no-cache";
+
+#[derive(Debug, Deserialize)]
+pub struct UploadParams {
+    #[serde(default)]
+    atomic: bool,
+}
+
+// 文件边接收边写入本节点并转发给副本节点，不在内存中缓存整个文件
+// atomic=true 时所有文件在一个批次中提交，返回整批是否成功
+// TTL（秒）通过 X-TTL 请求头或名为 ttl 的表单字段设置，表单字段作用于其后的所有文件
 #[handler]
-pub async fn upload(mut multipart: Multipart) -> Result<Json<Vec<FileHead>>> {
+pub async fn upload(
+    Query(params): Query<UploadParams>,
+    headers: &HeaderMap,
+    multipart: Multipart,
+) -> Result<Response> {
+    // 在单独的任务中接收，客户端中途断开时也能删除已写入的分块
+    task::spawn(receive_upload(params, headers.clone(), multipart)).await?
+}
+
+async fn receive_upload(
+    params: UploadParams,
+    headers: HeaderMap,
+    mut multipart: Multipart,
+) -> Result<Response> {
+    let mut expires_at = None;
+    if let Some(ttl) = headers.get("X-TTL") {
+        let ttl = ttl.to_str().unwrap_or_default();
+        match parse_ttl(ttl)? {
+            Some(ttl) => expires_at = Some(ttl),
+            None => return Ok(invalid_ttl(ttl)),
+        }
+    }
+
+    // 文件大小在接收完之前未知，先按请求体的大小检查容量，超过高水位时整个请求返回 507；
+    // 没有 Content-Length 的请求体（如分块传输）在写入每个分块时检查
+    let size = headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or_default();
+    if let Err(err) = capacity_service::check(size).await {
+        if !err.is::<InsufficientStorageError>() {
+            return Err(err);
+        }
+        info!("reject upload: {}", err);
+        return Ok(Response::builder()
+            .status(StatusCode::INSUFFICIENT_STORAGE)
+            .header("Content-Type", "application/json")
+            .body(format!("{{\"success\": false, \"msg\":\"{}\"}}", err)));
+    }
+
+    let replicas = select_replicas(size).await;
+    let (mut senders, mut tasks) = open_replicas(replicas);
+
     let mut files: Vec<FileHead> = Vec::new();
-    let mut internal_files: Vec<InternalFile> = Vec::new();
-    while let Ok(Some(field)) = multipart.next_field().await {
-        let file_name = field.file_name().map(ToString::to_string);
-        if let Ok(bytes) = field.bytes().await {
-            let file_info = FileInfo::new(
-                crypto::sha256_digest(&bytes),
-                file_name.unwrap(),
-                bytes.len(),
-                bytes,
-            );
-            let data = serde_json::to_vec(&file_info).unwrap();
-            let file_hash = file_info.file_hash.clone();
-            let file_name = file_info.file_name;
-            let ret = file_service::save(file_hash.as_str(), data.clone()).await;
-            let success = match ret {
-                Ok(_) => true,
-                Err(_) => false,
-            };
-            files.push(FileHead::new(
-                success,
-                file_hash.clone(),
-                file_name.clone(),
-                file_info.size,
-            ));
-            internal_files.push(InternalFile {
-                hash: file_hash,
-                name: file_name,
-                content: data,
-                size: file_info.size as i64,
-            });
+    let mut staged: Vec<StagedFile> = Vec::new();
+    loop {
+        // 请求体读取失败时不能当作已接收完，否则会提交不完整的批次
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(err) => {
+                discard_all(staged).await;
+                return Err(anyhow!("read multipart field: {}", err));
+            }
+        };
+        if field.name() == Some("ttl") && field.file_name().is_none() {
+            let ttl = field.text().await.unwrap_or_default();
+            match parse_ttl(&ttl)? {
+                Some(ttl) => expires_at = Some(ttl),
+                None => {
+                    discard_all(staged).await;
+                    return Ok(invalid_ttl(&ttl));
+                }
+            }
+            continue;
+        }
+        let file_name = match field.file_name() {
+            Some(file_name) => file_name.to_string(),
+            None => {
+                info!("skip field {:?} without file name", field.name());
+                continue;
+            }
+        };
+
+        let stream = stream_file(field, &file_name, expires_at, params.atomic, &mut senders);
+        let file = match stream.await {
+            Ok(file) => file,
+            Err(err) => {
+                discard_all(staged).await;
+                let status = if err.is::<FileTooLargeError>() {
+                    StatusCode::PAYLOAD_TOO_LARGE
+                } else if err.is::<InsufficientStorageError>() {
+                    StatusCode::INSUFFICIENT_STORAGE
+                } else {
+                    return Err(err);
+                };
+                info!("reject upload: {}", err);
+                return Ok(Response::builder()
+                    .status(status)
+                    .header("Content-Type", "application/json")
+                    .body(format!("{{\"success\": false, \"msg\":\"{}\"}}", err)));
+            }
+        };
+        if params.atomic {
+            staged.push(file);
+            continue;
         }
+        let success = file_service::save_staged(&file).await.is_ok();
+        files.push(file_head(&file, success));
     }
 
-    // 除本节点外再选 min_count - 1 个节点
-    let mut count = config::CONFIG.cluster.min_count - 1;
+    if params.atomic {
+        let result = file_service::save_staged_all(&staged).await;
+        let success = result.is_ok();
+        files.extend(staged.iter().map(|file| file_head(file, success)));
+        if let Err(err) = result {
+            info!("atomic upload failed: {:?}", err);
+            let batch = BatchHead::new(false, Some(err.to_string()), files, 0);
+            return Ok(Json(batch).into_response());
+        }
+    }
+
+    // 本节点保存完成后通知副本节点提交
+    forward(
+        &mut senders,
+        UploadChunk {
+            end: true,
+            ..Default::default()
+        },
+    )
+    .await;
+    drop(senders);
+    let mut replicated = 0;
+    let mut failures: Vec<String> = Vec::new();
+    while let Some(resp) = tasks.join_next().await {
+        info!("{:?}", resp);
+        match resp {
+            Ok(Ok(resp)) if resp.files.iter().all(|file| file.success) => replicated += 1,
+            Ok(Ok(resp)) => failures.push(format!("replica rejected {:?}", resp.files)),
+            Ok(Err(err)) => failures.push(err.to_string()),
+            Err(err) => failures.push(err.to_string()),
+        }
+    }
+
+    if params.atomic {
+        // 本节点已整批提交，副本节点未能整批提交时 success 为 false
+        let msg = (!failures.is_empty()).then(|| failures.join("; "));
+        let batch = BatchHead::new(failures.is_empty(), msg, files, replicated);
+        return Ok(Json(batch).into_response());
+    }
+    Ok(Json(files).into_response())
+}
+
+// 除本节点外再选 min_count - 1 个节点，跳过容量已满或无法连接的节点
+async fn select_replicas(size: u64) -> Vec<String> {
+    let count = config::CONFIG.cluster.min_count - 1;
 
     let mut servers = config::CONFIG.cluster.servers.clone();
-    let mut local = String::from("http://") + &config::CONFIG.server.grpc_address;
+    let local = String::from("http://") + &config::CONFIG.server.grpc_address;
     servers.remove(&local);
-    let mut new_servers: Vec<&String> = servers.iter().collect();
+    let mut new_servers: Vec<String> = servers.into_iter().collect();
 
     {
         //  match ret.await {
@@ -67,37 +208,205 @@ pub async fn upload(mut multipart: Multipart) -> Result<Json<Vec<FileHead>>> {
 
         let mut rng = rand::thread_rng();
         new_servers.shuffle(&mut rng);
-        new_servers.truncate(count);
     }
 
-    let mut tasks = JoinSet::new();
+    let mut replicas: Vec<String> = Vec::new();
     for server in new_servers {
-        tasks.spawn(upload_other(
-            UploadRequest {
-                files: internal_files.clone(),
-            },
-            server.clone(),
-        ));
+        if replicas.len() >= count {
+            break;
+        }
+        match capacity_other(size, &server).await {
+            Ok(false) => replicas.push(server),
+            Ok(true) => info!("{} is full, skip", server),
+            Err(err) => info!("capacity of {}, result: {:?}", server, err),
+        }
+    }
+    replicas
+}
+
+// 为每个副本节点打开一个流式上传
+fn open_replicas(
+    replicas: Vec<String>,
+) -> (
+    Vec<mpsc::Sender<UploadChunk>>,
+    JoinSet<Result<UploadResponse>>,
+) {
+    let mut senders = Vec::new();
+    let mut tasks = JoinSet::new();
+    for server in replicas {
+        let (sender, receiver) = mpsc::channel(REPLICA_BUFFER);
+        tasks.spawn(upload_stream_other(receiver, server));
+        senders.push(sender);
+    }
+    (senders, tasks)
+}
+
+// 把本节点已保存的文件按分块发送给副本节点，用于续传完成的文件
+pub async fn replicate(file_hash: &str, file_name: &str, expires_at: Option<u64>) -> Result<()> {
+    let manifest = file_service::find_manifest(file_hash).await?;
+    let replicas = select_replicas(manifest.size as u64).await;
+    if replicas.is_empty() {
+        return Ok(());
+    }
+    let (mut senders, mut tasks) = open_replicas(replicas);
+
+    let start = UploadChunk {
+        name: file_name.to_string(),
+        expires_at: expires_at.unwrap_or_default(),
+        start: true,
+        ..Default::default()
+    };
+    forward(&mut senders, start).await;
+    for index in 0..manifest.chunks.len() {
+        let content = UploadChunk {
+            content: file_service::read_chunk(&manifest, index).await?,
+            ..Default::default()
+        };
+        forward(&mut senders, content).await;
     }
+    let end = UploadChunk {
+        hash: file_hash.to_string(),
+        finish: true,
+        end: true,
+        ..Default::default()
+    };
+    forward(&mut senders, end).await;
+
+    drop(senders);
     while let Some(resp) = tasks.join_next().await {
         info!("{:?}", resp);
     }
+    Ok(())
+}
 
-    Ok(Json(files))
+// 返回过期时间，ttl 不是正整数或过期时间溢出时返回 None
+pub fn parse_ttl(ttl: &str) -> Result<Option<u64>> {
+    match ttl.trim().parse::<u64>() {
+        Ok(ttl) if ttl > 0 => {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+            Ok(now.checked_add(ttl))
+        }
+        _ => Ok(None),
+    }
+}
+
+pub fn invalid_ttl(ttl: &str) -> Response {
+    Response::builder()
+        .status(StatusCode::BAD_REQUEST)
+        .header("Content-Type", "application/json")
+        .body(format!(
+            "{{\"success\": false, \"msg\":\"invalid ttl {}\"}}",
+            ttl
+        ))
+}
+
+// 按分块读取文件内容，写入本节点的同时转发给副本节点
+async fn stream_file(
+    field: Field,
+    file_name: &str,
+    expires_at: Option<u64>,
+    atomic: bool,
+    senders: &mut Vec<mpsc::Sender<UploadChunk>>,
+) -> Result<StagedFile> {
+    let mut writer = Upload::new(file_name, expires_at).await?;
+    let start = UploadChunk {
+        name: file_name.to_string(),
+        expires_at: expires_at.unwrap_or_default(),
+        atomic,
+        start: true,
+        ..Default::default()
+    };
+    forward(senders, start).await;
+
+    let mut reader = field.into_async_read();
+    let mut buffer = vec![0u8; config::CONFIG.database.chunk_size];
+    loop {
+        let len = match fill_buffer(&mut reader, &mut buffer).await {
+            Ok(len) => len,
+            Err(err) => {
+                writer.abort().await;
+                return Err(err.into());
+            }
+        };
+        if len == 0 {
+            break;
+        }
+        if let Err(err) = writer.write(&buffer[..len]).await {
+            writer.abort().await;
+            return Err(err);
+        }
+        let content = UploadChunk {
+            content: buffer[..len].to_vec(),
+            ..Default::default()
+        };
+        forward(senders, content).await;
+    }
+
+    let file = writer.finish().await?;
+    let end = UploadChunk {
+        hash: file.hash().to_string(),
+        finish: true,
+        ..Default::default()
+    };
+    forward(senders, end).await;
+    Ok(file)
+}
+
+// 读满缓冲区或读到结尾，返回读取的字节数
+async fn fill_buffer(
+    reader: &mut (impl AsyncRead + Unpin),
+    buffer: &mut [u8],
+) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buffer.len() {
+        let read = reader.read(&mut buffer[len..]).await?;
+        if read == 0 {
+            break;
+        }
+        len += read;
+    }
+    Ok(len)
+}
+
+// 转发给所有副本节点，副本节点出错断开后不再转发
+async fn forward(senders: &mut Vec<mpsc::Sender<UploadChunk>>, chunk: UploadChunk) {
+    let mut alive = Vec::with_capacity(senders.len());
+    for sender in senders.drain(..) {
+        if sender.send(chunk.clone()).await.is_ok() {
+            alive.push(sender);
+        }
+    }
+    *senders = alive;
 }
 
+async fn discard_all(files: Vec<StagedFile>) {
+    for file in files {
+        file.discard().await;
+    }
+}
+
+fn file_head(file: &StagedFile, success: bool) -> FileHead {
+    FileHead::new(
+        success,
+        file.hash().to_string(),
+        file.name().to_string(),
+        file.size(),
+    )
+}
+
+// 支持 Range 请求，If-Range 与内容 hash 不一致时返回完整内容
+// If-None-Match 与内容 hash 一致时返回 304，不读取内容
 #[handler]
-pub async fn download(Path(file_hash): Path<String>) -> Response {
+pub async fn download(Path(file_hash): Path<String>, headers: &HeaderMap) -> Response {
+    if none_match(headers, &file_hash) {
+        if let Ok(Some(stat)) = file_service::stat(file_hash.as_str()).await {
+            return not_modified(&stat.file_hash, &immutable(stat.expires_at));
+        }
+    }
+
     // 先查询本节点，有则返回
-    if let Ok(f) = file_service::find(file_hash.as_str()).await {
-        return Response::builder()
-            .status(StatusCode::OK)
-            .header("Content-Type", "application/octet-stream")
-            .header(
-                "Content-Disposition",
-                "attachment;filename=".to_string() + f.file_name.as_str(),
-            )
-            .body(f.content);
+    if let Ok(content) = file_service::open(file_hash.as_str()).await {
+        return local_response(headers, content, immutable);
     }
 
     // 本节点未查询到，从其他节点查询
@@ -129,7 +438,7 @@ pub async fn download(Path(file_hash): Path<String>) -> Response {
             Err(err) => info!("sending to {}, result: {:?}", server, err),
         }
     }
-    if internal_server.len() == 0 {
+    if internal_server.is_empty() {
         info!("file not exists");
         return Response::builder()
             .status(StatusCode::OK)
@@ -138,7 +447,13 @@ pub async fn download(Path(file_hash): Path<String>) -> Response {
     }
     info!("exists in {}", internal_server);
 
-    let resp = download_other(&file_hash, &internal_server);
+    if none_match(headers, &file_hash) {
+        if let Ok(stat) = stat_other(&file_hash, &internal_server).await {
+            return not_modified(&stat.file_hash, &immutable(stat.expires_at));
+        }
+    }
+
+    let resp = download_other(&file_hash, &internal_server, headers);
     let resp = resp.await;
 
     match resp {
@@ -150,27 +465,403 @@ pub async fn download(Path(file_hash): Path<String>) -> Response {
     }
 }
 
-async fn upload_other(upload_request: UploadRequest, server: String) -> Result<String> {
-    let mut client = InternalFilesClient::connect(server.clone()).await?;
-    let request = tonic::Request::new(upload_request);
+// 只返回文件的元数据，不读取内容
+#[handler]
+pub async fn head(Path(file_hash): Path<String>, headers: &HeaderMap) -> Response {
+    if let Ok(Some(stat)) = file_service::stat(file_hash.as_str()).await {
+        return stat_response(headers, &stat);
+    }
 
-    let result = client.upload(request).await;
-    match result {
-        Ok(response) => Ok(format!(
-            "send to {}, response: {:?}",
-            server,
-            response.into_inner().files
-        )),
-        Err(err) => Ok(format!("send to {} failed, response: {:?}", server, err)),
+    // 本节点未查询到，依次向其他节点查询
+    let mut servers = config::CONFIG.cluster.servers.clone();
+    let local = String::from("http://") + &config::CONFIG.server.grpc_address;
+    servers.remove(&local);
+    for server in servers.iter() {
+        match stat_other(&file_hash, server).await {
+            Ok(stat) => return stat_response(headers, &stat),
+            Err(err) => info!("stat {} from {}, result: {:?}", file_hash, server, err),
+        }
     }
+    info!("file not exists");
+    Response::builder().status(StatusCode::NOT_FOUND).finish()
 }
 
-async fn exists_other(file_hash: &String, server: &String) -> Result<bool> {
+fn stat_response(headers: &HeaderMap, stat: &FileStat) -> Response {
+    let cache_control = immutable(stat.expires_at);
+    if none_match(headers, &stat.file_hash) {
+        return not_modified(&stat.file_hash, &cache_control);
+    }
+    let modified = UNIX_EPOCH + Duration::from_secs(stat.created_at);
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/octet-stream")
+        .header(
+            "Content-Disposition",
+            "attachment;filename=".to_string() + stat.file_name.as_str(),
+        )
+        .header(header::CONTENT_LENGTH, stat.size)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::ETAG, etag(&stat.file_hash))
+        .header(header::CACHE_CONTROL, cache_control)
+        .typed_header(LastModified::from(modified))
+        .finish()
+}
+
+// 内容按 hash 寻址，hash 即为强 ETag
+fn etag(hash: &str) -> String {
+    format!("\"{}\"", hash)
+}
+
+// 按 hash 下载的内容不会变化，带 TTL 的文件缓存时间不超过剩余的存活时间
+fn immutable(expires_at: Option<u64>) -> String {
+    let mut max_age = config::CONFIG.server.cache_max_age;
+    if let Some(expires_at) = expires_at {
+        max_age = max_age.min(expires_at.saturating_sub(now_secs()));
+    }
+    format!("public, max-age={}, immutable", max_age)
+}
+
+// If-None-Match 按弱比较匹配内容 hash，* 匹配任意存在的文件
+fn none_match(headers: &HeaderMap, hash: &str) -> bool {
+    match (
+        headers.typed_get::<IfNoneMatch>(),
+        etag(hash).parse::<ETag>(),
+    ) {
+        (Some(if_none_match), Ok(etag)) => !if_none_match.precondition_passes(&etag),
+        _ => false,
+    }
+}
+
+fn not_modified(hash: &str, cache_control: &str) -> Response {
+    Response::builder()
+        .status(StatusCode::NOT_MODIFIED)
+        .header(header::ETAG, etag(hash))
+        .header(header::CACHE_CONTROL, cache_control)
+        .finish()
+}
+
+#[handler]
+pub async fn download_name(Path(file_name): Path<String>, headers: &HeaderMap) -> Response {
+    if let Ok(content) = file_service::open_by_name(file_name.as_str()).await {
+        return local_response(headers, content, |_| REVALIDATE.to_string());
+    }
+
+    // 本节点未查询到，依次向其他节点按文件名下载
+    let mut servers = config::CONFIG.cluster.servers.clone();
+    let local = String::from("http://") + &config::CONFIG.server.grpc_address;
+    servers.remove(&local);
+    for server in servers.iter() {
+        match fetch_other("", &file_name, server).await {
+            Ok(res) => {
+                let content = Source::Memory(res.content);
+                return content_response(headers, &res.hash, &res.name, content, REVALIDATE);
+            }
+            Err(err) => info!("download {} from {}, result: {:?}", file_name, server, err),
+        }
+    }
+    info!("file not exists");
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("Content-Type", "application/json")
+        .body("{\"exists\": false, \"msg\":\"file not exists\"}")
+}
+
+// 响应的内容：已读入内存的完整内容，或按分块读取的大文件
+enum Source {
+    Memory(Vec<u8>),
+    Chunked(Box<FileReader>),
+}
+
+// 响应体的一段：multipart/byteranges 的分隔内容，或文件的一个区间
+enum Segment {
+    Bytes(Vec<u8>),
+    Range(ByteRange),
+}
+
+// cache_control 按文件的过期时间生成
+fn local_response(
+    headers: &HeaderMap,
+    content: FileContent,
+    cache_control: impl Fn(Option<u64>) -> String,
+) -> Response {
+    match content {
+        FileContent::Loaded(f) => content_response(
+            headers,
+            &f.file_hash,
+            &f.file_name,
+            Source::Memory(f.content),
+            &cache_control(f.expires_at),
+        ),
+        FileContent::Chunked(reader) => {
+            let (hash, name) = (reader.hash().to_string(), reader.name().to_string());
+            let cache_control = cache_control(reader.expires_at());
+            content_response(
+                headers,
+                &hash,
+                &name,
+                Source::Chunked(reader),
+                &cache_control,
+            )
+        }
+    }
+}
+
+// 按 Range 请求头返回完整内容、单个区间或 multipart/byteranges
+fn content_response(
+    headers: &HeaderMap,
+    hash: &str,
+    name: &str,
+    source: Source,
+    cache_control: &str,
+) -> Response {
+    if none_match(headers, hash) {
+        return not_modified(hash, cache_control);
+    }
+    let size = match &source {
+        Source::Memory(content) => content.len() as u64,
+        Source::Chunked(reader) => reader.size() as u64,
+    };
+    let ranges = match headers.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(range) if if_range_matches(headers, hash) => range::parse(range, size),
+        _ => Ranges::Full,
+    };
+    let disposition = "attachment;filename=".to_string() + name;
+    let builder = Response::builder()
+        .header(header::ETAG, etag(hash))
+        .header(header::CACHE_CONTROL, cache_control)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header("Content-Disposition", disposition);
+
+    match ranges {
+        Ranges::Full => {
+            let segments = match size {
+                0 => vec![],
+                _ => vec![Segment::Range(ByteRange {
+                    start: 0,
+                    end: size - 1,
+                })],
+            };
+            builder
+                .status(StatusCode::OK)
+                .header("Content-Type", "application/octet-stream")
+                .body(content_body(source, segments))
+        }
+        Ranges::Unsatisfiable => builder
+            .status(StatusCode::RANGE_NOT_SATISFIABLE)
+            .header(header::CONTENT_RANGE, format!("bytes */{}", size))
+            .finish(),
+        Ranges::Partial(ranges) if ranges.len() == 1 => {
+            let range = ranges[0];
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header("Content-Type", "application/octet-stream")
+                .header(header::CONTENT_RANGE, range.content_range(size))
+                .body(content_body(source, vec![Segment::Range(range)]))
+        }
+        Ranges::Partial(ranges) => {
+            let boundary = HEXLOWER.encode(&rand::random::<[u8; 16]>());
+            let mut segments = Vec::new();
+            for range in ranges {
+                let part = format!(
+                    "--{}\r\nContent-Type: application/octet-stream\r\nContent-Range: {}\r\n\r\n",
+                    boundary,
+                    range.content_range(size)
+                );
+                segments.push(Segment::Bytes(part.into_bytes()));
+                segments.push(Segment::Range(range));
+                segments.push(Segment::Bytes(b"\r\n".to_vec()));
+            }
+            segments.push(Segment::Bytes(format!("--{}--\r\n", boundary).into_bytes()));
+            builder
+                .status(StatusCode::PARTIAL_CONTENT)
+                .header(
+                    "Content-Type",
+                    format!("multipart/byteranges; boundary={}", boundary),
+                )
+                .body(content_body(source, segments))
+        }
+    }
+}
+
+fn content_body(source: Source, segments: Vec<Segment>) -> Body {
+    match source {
+        Source::Memory(content) => {
+            let mut body = Vec::new();
+            for segment in segments {
+                match segment {
+                    Segment::Bytes(bytes) => body.extend_from_slice(&bytes),
+                    Segment::Range(range) => {
+                        body.extend_from_slice(&content[range.start as usize..=range.end as usize])
+                    }
+                }
+            }
+            Body::from(body)
+        }
+        Source::Chunked(reader) => {
+            let (sender, receiver) = mpsc::channel(DOWNLOAD_BUFFER);
+            tokio::spawn(send_segments(reader, segments, sender));
+            Body::from_bytes_stream(ReceiverStream::new(receiver))
+        }
+    }
+}
+
+// 逐块读取区间内的内容发送给响应体，读取失败时以错误结束响应，客户端可据此判断内容不完整
+async fn send_segments(
+    mut reader: Box<FileReader>,
+    segments: Vec<Segment>,
+    sender: mpsc::Sender<io::Result<Vec<u8>>>,
+) {
+    let chunk_size = reader.chunk_size() as u64;
+    for segment in segments {
+        let range = match segment {
+            Segment::Bytes(bytes) => {
+                if sender.send(Ok(bytes)).await.is_err() {
+                    return;
+                }
+                continue;
+            }
+            Segment::Range(range) => range,
+        };
+        for index in range.start / chunk_size..=range.end / chunk_size {
+            let data = match reader.read_chunk(index as usize).await {
+                Ok(chunk) => {
+                    let offset = index * chunk_size;
+                    let from = range.start.saturating_sub(offset) as usize;
+                    let to = (range.end + 1 - offset).min(chunk.len() as u64) as usize;
+                    Ok(chunk[from..to].to_vec())
+                }
+                Err(err) => {
+                    info!("read {} failed: {:?}", reader.hash(), err);
+                    Err(io::Error::other(err.to_string()))
+                }
+            };
+            let failed = data.is_err();
+            if sender.send(data).await.is_err() || failed {
+                return;
+            }
+        }
+    }
+}
+
+// If-Range 只支持强 ETag，即带引号的内容 hash，日期等其他形式视为不匹配
+fn if_range_matches(headers: &HeaderMap, hash: &str) -> bool {
+    match headers.get(header::IF_RANGE) {
+        Some(value) => value.to_str().is_ok_and(|value| value.trim() == etag(hash)),
+        None => true,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListParams {
+    cursor: Option<String>,
+    limit: Option<usize>,
+}
+
+#[handler]
+pub async fn list(Query(params): Query<ListParams>) -> Result<Json<FileList>> {
+    let limit = params.limit.unwrap_or(100).clamp(1, 1000);
+    let cursor = params.cursor.filter(|cursor| !cursor.is_empty());
+    let files = file_service::list(cursor.as_deref(), limit).await?;
+    Ok(Json(files))
+}
+
+#[handler]
+pub async fn delete(Path(file_hash): Path<String>) -> Result<Json<DeleteHead>> {
+    let local = file_service::delete(file_hash.as_str()).await;
+    let request = DeleteRequest {
+        hash: file_hash.clone(),
+        name: String::new(),
+    };
+    let (success, deleted) = delete_all(local, request).await;
+    Ok(Json(DeleteHead::new(success, file_hash, deleted)))
+}
+
+// 删除文件名的引用，file_hash 为本节点上该文件名指向的内容
+#[handler]
+pub async fn delete_name(Path(file_name): Path<String>) -> Result<Json<DeleteHead>> {
+    let file_hash = file_service::resolve(file_name.as_str())
+        .await?
+        .unwrap_or_default();
+    let local = file_service::delete_name(file_name.as_str()).await;
+    let request = DeleteRequest {
+        hash: String::new(),
+        name: file_name,
+    };
+    let (success, deleted) = delete_all(local, request).await;
+    Ok(Json(DeleteHead::new(success, file_hash, deleted)))
+}
+
+// 汇总本节点和其他所有节点的删除结果，返回是否全部成功及删除了的节点数
+async fn delete_all(local: Result<bool>, delete_request: DeleteRequest) -> (bool, usize) {
+    let mut success = true;
+    let mut deleted = 0;
+    match local {
+        Ok(true) => deleted += 1,
+        Ok(false) => {}
+        Err(err) => {
+            info!("delete {:?} failed: {:?}", delete_request, err);
+            success = false;
+        }
+    }
+
+    // 副本可能在任意节点上，通知其他所有节点删除
+    let mut servers = config::CONFIG.cluster.servers.clone();
+    let local = String::from("http://") + &config::CONFIG.server.grpc_address;
+    servers.remove(&local);
+
+    let mut tasks = JoinSet::new();
+    for server in servers {
+        tasks.spawn(delete_other(delete_request.clone(), server));
+    }
+    while let Some(resp) = tasks.join_next().await {
+        match resp {
+            Ok(Ok(true)) => deleted += 1,
+            Ok(Ok(false)) => {}
+            Ok(Err(err)) => {
+                info!("{:?}", err);
+                success = false;
+            }
+            Err(err) => {
+                info!("{:?}", err);
+                success = false;
+            }
+        }
+    }
+    (success, deleted)
+}
+
+async fn upload_stream_other(
+    receiver: mpsc::Receiver<UploadChunk>,
+    server: String,
+) -> Result<UploadResponse> {
+    let mut client = InternalFilesClient::connect(server.clone())
+        .await
+        .map_err(|err| anyhow!("connect to {} failed: {:?}", server, err))?;
+    let request = tonic::Request::new(ReceiverStream::new(receiver));
+
+    let response = client
+        .upload_stream(request)
+        .await
+        .map_err(|err| anyhow!("send to {} failed: {:?}", server, err))?;
+    Ok(response.into_inner())
+}
+
+async fn capacity_other(size: u64, server: &str) -> Result<bool> {
+    let capacity_request = CapacityRequest { size };
+
+    let mut client = InternalFilesClient::connect(server.to_string()).await?;
+    let request = tonic::Request::new(capacity_request);
+
+    let response = client.capacity(request).await?;
+    Ok(response.into_inner().full)
+}
+
+async fn exists_other(file_hash: &str, server: &str) -> Result<bool> {
     let exists_request = ExistsRequest {
         hash: file_hash.to_string(),
     };
 
-    let mut client = InternalFilesClient::connect(server.clone()).await?;
+    let mut client = InternalFilesClient::connect(server.to_string()).await?;
     let request = tonic::Request::new(exists_request);
 
     let result = client.exists(request).await;
@@ -180,27 +871,62 @@ async fn exists_other(file_hash: &String, server: &String) -> Result<bool> {
     }
 }
 
-async fn download_other(file_hash: &String, server: &String) -> Result<Response> {
+async fn delete_other(delete_request: DeleteRequest, server: String) -> Result<bool> {
+    let mut client = InternalFilesClient::connect(server.clone()).await?;
+    let request = tonic::Request::new(delete_request);
+
+    let response = client.delete(request).await?;
+    Ok(response.into_inner().deleted)
+}
+
+async fn stat_other(file_hash: &str, server: &str) -> Result<FileStat> {
+    let stat_request = StatRequest {
+        hash: file_hash.to_string(),
+    };
+
+    let mut client = InternalFilesClient::connect(server.to_string()).await?;
+    let request = tonic::Request::new(stat_request);
+
+    let res = client.stat(request).await?.into_inner();
+    Ok(FileStat {
+        file_hash: res.hash,
+        file_name: res.name,
+        size: res.size as usize,
+        created_at: res.created_at,
+        expires_at: (res.expires_at > 0).then_some(res.expires_at),
+    })
+}
+
+// 通过下载 RPC 从其他节点获取文件，按 hash 或文件名查找
+pub async fn fetch_other(
+    file_hash: &str,
+    file_name: &str,
+    server: &str,
+) -> Result<DownloadResponse> {
     let download_request = DownloadRequest {
         hash: file_hash.to_string(),
+        name: file_name.to_string(),
     };
 
-    let mut client = InternalFilesClient::connect(server.clone()).await?;
+    let mut client = InternalFilesClient::connect(server.to_string()).await?;
     let request = tonic::Request::new(download_request);
 
-    let result = client.download(request).await;
+    let response = client.download(request).await?;
+    Ok(response.into_inner())
+}
+
+async fn download_other(file_hash: &str, server: &str, headers: &HeaderMap) -> Result<Response> {
+    let result = fetch_other(file_hash, "", server).await;
     match result {
-        Ok(response) => {
-            let res = response.into_inner();
-            let r = Response::builder()
-                .status(StatusCode::OK)
-                .header("Content-Type", "application/octet-stream")
-                .header(
-                    "Content-Disposition",
-                    "attachment;filename=".to_string() + res.name.as_str(),
-                )
-                .body(res.content);
-            Ok(r)
+        Ok(res) => {
+            let cache_control = immutable((res.expires_at > 0).then_some(res.expires_at));
+            Ok(content_response(
+                headers,
+                file_hash,
+                &res.name,
+                Source::Memory(res.content),
+                &cache_control,
+            ))
         }
         Err(err) => {
             let r = Response::builder()
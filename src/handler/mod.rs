@@ -1,3 +1,5 @@
+pub mod admin_handler;
 pub mod common_handler;
 pub mod file_grpc_handler;
 pub mod file_handler;
+pub mod tus_handler;
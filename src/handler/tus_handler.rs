@@ -0,0 +1,286 @@
+use crate::config;
+use crate::handler::file_handler::{self, invalid_ttl, parse_ttl};
+use crate::service::capacity_service::{self, InsufficientStorageError};
+use crate::service::file_service::{self, FileTooLargeError, ResumableUpload, UploadError};
+use anyhow::Result;
+use data_encoding::BASE64;
+use poem::{
+    handler,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    web::{
+        headers::{Expires, HeaderMapExt},
+        Path,
+    },
+    Body, Response,
+};
+use std::collections::HashMap;
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::task;
+use tracing::info;
+
+// 支持的 tus 协议版本
+const TUS_VERSION: &str = "1.0.0";
+
+const TUS_EXTENSION: &str = "creation,termination,expiration";
+
+// PATCH 请求体的类型
+const OFFSET_OCTET_STREAM: &str = "application/offset+octet-stream";
+
+// 返回服务端支持的 tus 版本和扩展
+#[handler]
+pub fn options() -> Response {
+    Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header("Tus-Resumable", TUS_VERSION)
+        .header("Tus-Version", TUS_VERSION)
+        .header("Tus-Extension", TUS_EXTENSION)
+        .header("Tus-Max-Size", config::CONFIG.upload.max_file_size)
+        .finish()
+}
+
+// 创建上传，Upload-Metadata 中的 filename（或 name）为文件名，ttl 为文件的存活时间（秒）
+// Upload-Length 为 0 时直接保存为空文件
+#[handler]
+pub async fn create(headers: &HeaderMap) -> Result<Response> {
+    if let Some(resp) = check_version(headers) {
+        return Ok(resp);
+    }
+    let length = match header_u64(headers, "Upload-Length") {
+        Some(length) => length,
+        None => return Ok(error(StatusCode::BAD_REQUEST, "invalid Upload-Length")),
+    };
+
+    let metadata = match headers.get("Upload-Metadata") {
+        Some(value) => match parse_metadata(value.to_str().unwrap_or_default()) {
+            Some(metadata) => metadata,
+            None => return Ok(error(StatusCode::BAD_REQUEST, "invalid Upload-Metadata")),
+        },
+        None => HashMap::new(),
+    };
+    let file_name = match metadata.get("filename").or_else(|| metadata.get("name")) {
+        Some(file_name) if !file_name.is_empty() => file_name.clone(),
+        _ => {
+            return Ok(error(
+                StatusCode::BAD_REQUEST,
+                "missing filename in Upload-Metadata",
+            ))
+        }
+    };
+
+    let ttl = match metadata.get("ttl") {
+        Some(ttl) => Some(ttl.clone()),
+        None => headers
+            .get("X-TTL")
+            .map(|ttl| ttl.to_str().unwrap_or_default().to_string()),
+    };
+    let mut expires_at = None;
+    if let Some(ttl) = ttl {
+        match parse_ttl(&ttl)? {
+            Some(ttl) => expires_at = Some(ttl),
+            None => return Ok(invalid_ttl(&ttl)),
+        }
+    }
+
+    if let Err(err) = capacity_service::check(length).await {
+        if !err.is::<InsufficientStorageError>() {
+            return Err(err);
+        }
+        info!("reject upload: {}", err);
+        return Ok(error(StatusCode::INSUFFICIENT_STORAGE, &err.to_string()));
+    }
+
+    let upload = match file_service::create_upload(&file_name, length, expires_at).await {
+        Ok(upload) => upload,
+        Err(err) => {
+            if !err.is::<FileTooLargeError>() {
+                return Err(err);
+            }
+            info!("reject upload: {}", err);
+            return Ok(error(StatusCode::PAYLOAD_TOO_LARGE, &err.to_string()));
+        }
+    };
+    let mut resp = Response::builder()
+        .status(StatusCode::CREATED)
+        .header("Tus-Resumable", TUS_VERSION)
+        .header(header::LOCATION, format!("/tus/{}", upload.id))
+        .header("Upload-Expires", upload_expires(&upload));
+    if let Some(hash) = &upload.hash {
+        replicate(hash, &upload);
+        resp = resp.header("X-File-Hash", hash);
+    }
+    Ok(resp.finish())
+}
+
+// 返回已接收的字节数，客户端从该位置继续上传
+#[handler]
+pub async fn head(Path(upload_id): Path<String>, headers: &HeaderMap) -> Result<Response> {
+    if let Some(resp) = check_version(headers) {
+        return Ok(resp);
+    }
+    let upload = match file_service::find_upload(&upload_id).await? {
+        Some(upload) => upload,
+        None => {
+            return Ok(Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header("Tus-Resumable", TUS_VERSION)
+                .header(header::CACHE_CONTROL, "no-store")
+                .finish())
+        }
+    };
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header("Tus-Resumable", TUS_VERSION)
+        .header(header::CACHE_CONTROL, "no-store")
+        .header("Upload-Offset", upload.offset)
+        .header("Upload-Length", upload.length)
+        .header("Upload-Expires", upload_expires(&upload))
+        .finish())
+}
+
+// 从 Upload-Offset 处追加内容，全部接收后保存为按 hash 寻址的文件并在后台复制到副本节点
+#[handler]
+pub async fn patch(
+    Path(upload_id): Path<String>,
+    headers: &HeaderMap,
+    body: Body,
+) -> Result<Response> {
+    if let Some(resp) = check_version(headers) {
+        return Ok(resp);
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if content_type != OFFSET_OCTET_STREAM {
+        return Ok(error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "Content-Type must be application/offset+octet-stream",
+        ));
+    }
+    let offset = match header_u64(headers, "Upload-Offset") {
+        Some(offset) => offset,
+        None => return Ok(error(StatusCode::BAD_REQUEST, "invalid Upload-Offset")),
+    };
+
+    // 在单独的任务中接收，客户端中途断开时也能保存已接收的内容
+    let id = upload_id.clone();
+    let result = task::spawn(async move {
+        file_service::append_upload(&id, offset, body.into_async_read()).await
+    })
+    .await?;
+    let upload = match result {
+        Err(err) if err.is::<InsufficientStorageError>() => {
+            info!("reject patch of upload {}: {}", upload_id, err);
+            return Ok(error(StatusCode::INSUFFICIENT_STORAGE, &err.to_string()));
+        }
+        Err(err) => {
+            let status = match err.downcast_ref::<UploadError>() {
+                Some(UploadError::NotFound) => StatusCode::NOT_FOUND,
+                Some(UploadError::Offset(_)) => StatusCode::CONFLICT,
+                Some(UploadError::Locked) => StatusCode::LOCKED,
+                Some(UploadError::Length(_)) => StatusCode::PAYLOAD_TOO_LARGE,
+                None => return Err(err),
+            };
+            info!("reject patch of upload {}: {}", upload_id, err);
+            return Ok(error(status, &err.to_string()));
+        }
+        Ok(upload) => upload,
+    };
+
+    let mut resp = Response::builder()
+        .status(StatusCode::NO_CONTENT)
+        .header("Tus-Resumable", TUS_VERSION)
+        .header("Upload-Offset", upload.offset)
+        .header("Upload-Expires", upload_expires(&upload));
+    if let Some(hash) = &upload.hash {
+        replicate(hash, &upload);
+        resp = resp.header("X-File-Hash", hash);
+    }
+    Ok(resp.finish())
+}
+
+// 终止上传并删除已接收的内容
+#[handler]
+pub async fn delete(Path(upload_id): Path<String>, headers: &HeaderMap) -> Result<Response> {
+    if let Some(resp) = check_version(headers) {
+        return Ok(resp);
+    }
+    match file_service::delete_upload(&upload_id).await {
+        Ok(true) => Ok(Response::builder()
+            .status(StatusCode::NO_CONTENT)
+            .header("Tus-Resumable", TUS_VERSION)
+            .finish()),
+        Ok(false) => Ok(error(StatusCode::NOT_FOUND, "upload not found")),
+        Err(err) => match err.downcast_ref::<UploadError>() {
+            Some(UploadError::Locked) => Ok(error(StatusCode::LOCKED, &err.to_string())),
+            _ => Err(err),
+        },
+    }
+}
+
+// 在后台复制到副本节点，响应不等待复制完成
+fn replicate(hash: &str, upload: &ResumableUpload) {
+    let hash = hash.to_string();
+    let name = upload.name.clone();
+    let expires_at = upload.expires_at;
+    task::spawn(async move {
+        if let Err(err) = file_handler::replicate(&hash, &name, expires_at).await {
+            info!("replicate {}, result: {:?}", hash, err);
+        }
+    });
+}
+
+// 除 OPTIONS 外的请求都需带上服务端支持的 Tus-Resumable
+fn check_version(headers: &HeaderMap) -> Option<Response> {
+    if headers.get("Tus-Resumable").map(HeaderValue::as_bytes) == Some(TUS_VERSION.as_bytes()) {
+        return None;
+    }
+    Some(
+        Response::builder()
+            .status(StatusCode::PRECONDITION_FAILED)
+            .header("Tus-Version", TUS_VERSION)
+            .finish(),
+    )
+}
+
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    let value = headers.get(name)?.to_str().ok()?;
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+// Upload-Metadata 由逗号分隔的键值对组成，值为 base64 编码，可以省略
+fn parse_metadata(value: &str) -> Option<HashMap<String, String>> {
+    let mut metadata = HashMap::new();
+    for pair in value.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let (key, value) = match pair.split_once(' ') {
+            Some((key, value)) => {
+                let value = BASE64.decode(value.trim().as_bytes()).ok()?;
+                (key, String::from_utf8(value).ok()?)
+            }
+            None => (pair, String::new()),
+        };
+        metadata.insert(key.to_string(), value);
+    }
+    Some(metadata)
+}
+
+// 未完成的上传被删除的时间，格式与 Expires 相同
+fn upload_expires(upload: &ResumableUpload) -> HeaderValue {
+    let expires = UNIX_EPOCH + Duration::from_secs(upload.upload_expires_at());
+    let mut headers = HeaderMap::new();
+    headers.typed_insert(Expires::from(expires));
+    headers
+        .remove(header::EXPIRES)
+        .unwrap_or_else(|| HeaderValue::from_static(""))
+}
+
+fn error(status: StatusCode, msg: &str) -> Response {
+    Response::builder()
+        .status(status)
+        .header("Tus-Resumable", TUS_VERSION)
+        .header("Content-Type", "application/json")
+        .body(format!("{{\"success\": false, \"msg\":\"{}\"}}", msg))
+}
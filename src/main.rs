@@ -1,14 +1,26 @@
-use anyhow::{Ok, Result};
+use anyhow::{anyhow, Ok, Result};
 use poem::{
-    error::NotFoundError, get, http::StatusCode, listener::TcpListener, post, EndpointExt,
+    error::NotFoundError, get, http::StatusCode, listener::TcpListener, patch, post, EndpointExt,
     Response, Route, Server,
 };
+use rust_storage::handler::admin_handler;
 use rust_storage::handler::common_handler::index;
 use rust_storage::handler::file_grpc_handler::{
-    internal_files::internal_files_server::InternalFilesServer, InternalFilesService,
+    internal_files::{
+        internal_files_client::InternalFilesClient, internal_files_server::InternalFilesServer,
+        CheckpointRequest,
+    },
+    InternalFilesService,
 };
-use rust_storage::handler::file_handler::{download, upload};
+use rust_storage::handler::file_handler::{
+    delete, delete_name, download, download_name, head, list, upload,
+};
+use rust_storage::handler::tus_handler;
+use rust_storage::service::backup_service;
+use rust_storage::service::key_service::{self, MasterKey};
+use rust_storage::service::{expire_service, file_service, scrub_service, tier_service};
 use rust_storage::{config, middleware_fn::log};
+use std::env;
 use tokio::join;
 use tracing::info;
 
@@ -16,6 +28,36 @@ use tracing::info;
 async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
 
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        None => {}
+        Some("rotate-key") => {
+            let path = args
+                .get(2)
+                .ok_or_else(|| anyhow!("usage: rotate-key <new key file>"))?;
+            return rotate_key(path).await;
+        }
+        Some("checkpoint") => return checkpoint(args.get(2)).await,
+        Some("restore") => {
+            let path = args
+                .get(2)
+                .ok_or_else(|| anyhow!("usage: restore <checkpoint dir>"))?;
+            return backup_service::restore(path);
+        }
+        Some(command) => return Err(anyhow!("unknown command {}", command)),
+    }
+
+    tokio::spawn(file_service::upgrade_legacy_all());
+    if config::CONFIG.scrub.enabled {
+        tokio::spawn(scrub_service::run());
+    }
+    if config::CONFIG.expire.enabled {
+        tokio::spawn(expire_service::run());
+    }
+    if config::CONFIG.tier.enabled {
+        tokio::spawn(tier_service::run());
+    }
+
     let http_server = http_server();
     let grpc_server = grpc_server();
     let _ = join!(http_server, grpc_server);
@@ -27,7 +69,22 @@ async fn http_server() -> Result<()> {
     let app = Route::new()
         .at("/", get(index))
         .at("/file/upload", post(upload))
-        .at("/file/:file_hash", get(download))
+        .at("/file/:file_hash", get(download).head(head).delete(delete))
+        .at("/name/:file_name", get(download_name).delete(delete_name))
+        .at("/files", get(list))
+        .at(
+            "/tus",
+            post(tus_handler::create).options(tus_handler::options),
+        )
+        .at(
+            "/tus/:upload_id",
+            patch(tus_handler::patch)
+                .head(tus_handler::head)
+                .delete(tus_handler::delete),
+        )
+        .at("/admin/checkpoint", post(admin_handler::checkpoint))
+        .at("/admin/capacity", get(admin_handler::capacity))
+        .at("/admin/cache", get(admin_handler::cache))
         .with(log::Log)
         .catch_error(|_: NotFoundError| async move {
             Response::builder()
@@ -50,3 +107,32 @@ async fn grpc_server() -> Result<()> {
         .await?;
     Ok(())
 }
+
+// 需在节点停止时执行，完成后将配置中的主密钥替换为新密钥
+async fn rotate_key(path: &str) -> Result<()> {
+    let new_key = MasterKey::load(path)?;
+    let count = key_service::rotate(&new_key).await?;
+    info!(
+        "{} files and uploads rewrapped with key {}",
+        count, new_key.id
+    );
+    Ok(())
+}
+
+// 节点运行时数据库被其独占，通过 grpc 请求运行中的节点创建快照，默认请求本节点
+async fn checkpoint(server: Option<&String>) -> Result<()> {
+    let server = match server {
+        Some(server) => server.clone(),
+        None => String::from("http://") + &config::CONFIG.server.grpc_address,
+    };
+    let mut client = InternalFilesClient::connect(server.clone()).await?;
+    let response = client
+        .checkpoint(tonic::Request::new(CheckpointRequest {}))
+        .await?;
+    info!(
+        "checkpoint created on {}: {}",
+        server,
+        response.into_inner().path
+    );
+    Ok(())
+}
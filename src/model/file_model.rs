@@ -1,4 +1,12 @@
+use crate::storage::Capacity;
+use crate::util::compress::Codec;
+use anyhow::{anyhow, Error, Result};
 use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub mod records {
+    include!("../proto/records.rs");
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct FileHead {
@@ -19,12 +27,106 @@ impl FileHead {
     }
 }
 
+// 原子上传的结果，本节点和所有副本节点都整批提交后 success 才为 true；
+// files 中的 success 为本节点的结果，replicas 为整批提交成功的副本节点数
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BatchHead {
+    pub success: bool,
+    pub msg: Option<String>,
+    pub files: Vec<FileHead>,
+    pub replicas: usize,
+}
+
+impl BatchHead {
+    pub fn new(success: bool, msg: Option<String>, files: Vec<FileHead>, replicas: usize) -> Self {
+        BatchHead {
+            success,
+            msg,
+            files,
+            replicas,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileList {
+    pub files: Vec<FileHead>,
+    pub next_cursor: Option<String>,
+}
+
+impl FileList {
+    pub fn new(files: Vec<FileHead>, next_cursor: Option<String>) -> Self {
+        FileList { files, next_cursor }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteHead {
+    pub success: bool,
+    pub file_hash: String,
+    pub deleted: usize,
+}
+
+impl DeleteHead {
+    pub fn new(success: bool, file_hash: String, deleted: usize) -> Self {
+        DeleteHead {
+            success,
+            file_hash,
+            deleted,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CheckpointHead {
+    pub success: bool,
+    pub path: String,
+}
+
+impl CheckpointHead {
+    pub fn new(success: bool, path: String) -> Self {
+        CheckpointHead { success, path }
+    }
+}
+
+// 不限制容量的存储引擎只返回 full
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CapacityHead {
+    pub full: bool,
+    pub total: Option<u64>,
+    pub used: Option<u64>,
+    pub available: Option<u64>,
+}
+
+impl CapacityHead {
+    pub fn new(full: bool, capacity: Option<Capacity>) -> Self {
+        CapacityHead {
+            full,
+            total: capacity.map(|c| c.total),
+            used: capacity.map(|c| c.used),
+            available: capacity.map(|c| c.available),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
+pub struct CacheHead {
+    pub entries: usize,
+    pub used: usize,
+    pub limit: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FileInfo {
     pub file_hash: String,
     pub file_name: String,
     pub size: usize,
     pub content: Vec<u8>,
+    // 过期时间（秒级时间戳），为空时永不过期
+    #[serde(default)]
+    pub expires_at: Option<u64>,
 }
 
 impl FileInfo {
@@ -34,6 +136,157 @@ impl FileInfo {
             file_name,
             size,
             content,
+            expires_at: None,
+        }
+    }
+}
+
+// 文件的元数据，不含内容
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FileStat {
+    pub file_hash: String,
+    pub file_name: String,
+    pub size: usize,
+    // 创建时间（秒级时间戳）
+    pub created_at: u64,
+    pub expires_at: Option<u64>,
+}
+
+impl From<&FileManifest> for FileStat {
+    fn from(manifest: &FileManifest) -> Self {
+        FileStat {
+            file_hash: manifest.file_hash.clone(),
+            file_name: manifest.file_name.clone(),
+            size: manifest.size,
+            created_at: manifest.created_at,
+            expires_at: manifest.expires_at,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FileManifest {
+    pub file_hash: String,
+    pub file_name: String,
+    pub size: usize,
+    pub chunk_size: usize,
+    pub chunks: Vec<String>,
+    // 引用该内容的所有文件名，数量即引用计数，file_name 为其中第一个
+    #[serde(default)]
+    pub names: Vec<String>,
+    #[serde(default)]
+    pub created_at: u64,
+    #[serde(default)]
+    pub codec: Codec,
+    // 加密文件的数据密钥由主密钥加密后保存，key_id 标识所用的主密钥
+    #[serde(default)]
+    pub key_id: Option<String>,
+    #[serde(default)]
+    pub wrapped_key: Option<String>,
+    #[serde(default)]
+    pub expires_at: Option<u64>,
+    // 最近一次读取的时间，用于判断是否移入冷存储
+    #[serde(default)]
+    pub accessed_at: u64,
+    // 内容分块是否在冷存储中
+    #[serde(default)]
+    pub cold: bool,
+}
+
+impl FileManifest {
+    pub fn new(
+        file_hash: String,
+        file_name: String,
+        size: usize,
+        chunk_size: usize,
+        chunks: Vec<String>,
+        codec: Codec,
+    ) -> Self {
+        let now = now_secs();
+        FileManifest {
+            file_hash,
+            names: vec![file_name.clone()],
+            file_name,
+            size,
+            chunk_size,
+            chunks,
+            created_at: now,
+            codec,
+            key_id: None,
+            wrapped_key: None,
+            expires_at: None,
+            accessed_at: now,
+            cold: false,
+        }
+    }
+
+    pub fn expired(&self) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| expires_at <= now_secs())
+    }
+
+    // 距最近一次读取（从未读取过则为创建）经过的秒数
+    pub fn idle_secs(&self) -> u64 {
+        now_secs().saturating_sub(self.accessed_at.max(self.created_at))
+    }
+}
+
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+impl From<&FileManifest> for records::FileManifest {
+    fn from(manifest: &FileManifest) -> Self {
+        let codec = match manifest.codec {
+            Codec::None => records::Codec::None,
+            Codec::Zstd => records::Codec::Zstd,
+            Codec::Lz4 => records::Codec::Lz4,
+        };
+        records::FileManifest {
+            file_hash: manifest.file_hash.clone(),
+            file_name: manifest.file_name.clone(),
+            size: manifest.size as u64,
+            chunk_size: manifest.chunk_size as u64,
+            chunks: manifest.chunks.clone(),
+            names: manifest.names.clone(),
+            created_at: manifest.created_at,
+            codec: codec as i32,
+            key_id: manifest.key_id.clone(),
+            wrapped_key: manifest.wrapped_key.clone(),
+            expires_at: manifest.expires_at,
+            accessed_at: manifest.accessed_at,
+            cold: manifest.cold,
         }
     }
 }
+
+impl TryFrom<records::FileManifest> for FileManifest {
+    type Error = Error;
+
+    fn try_from(record: records::FileManifest) -> Result<Self> {
+        let codec = match records::Codec::from_i32(record.codec) {
+            Some(records::Codec::None) => Codec::None,
+            Some(records::Codec::Zstd) => Codec::Zstd,
+            Some(records::Codec::Lz4) => Codec::Lz4,
+            None => return Err(anyhow!("unknown codec {}", record.codec)),
+        };
+        Ok(FileManifest {
+            file_hash: record.file_hash,
+            file_name: record.file_name,
+            size: record.size as usize,
+            chunk_size: record.chunk_size as usize,
+            chunks: record.chunks,
+            names: record.names,
+            created_at: record.created_at,
+            codec,
+            key_id: record.key_id,
+            wrapped_key: record.wrapped_key,
+            expires_at: record.expires_at,
+            accessed_at: record.accessed_at,
+            cold: record.cold,
+        })
+    }
+}
@@ -1,3 +1,4 @@
+/// 旧版本节点复制文件时使用，新版本节点使用 upload_stream
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct UploadRequest {
@@ -15,6 +16,32 @@ pub struct InternalFile {
     pub content: ::prost::alloc::vec::Vec<u8>,
     #[prost(int64, tag = "4")]
     pub size: i64,
+    /// 过期时间（秒级时间戳），0 表示永不过期
+    #[prost(uint64, tag = "5")]
+    pub expires_at: u64,
+}
+/// 流式上传的一条消息：start 为 true 时以 name 开始一个新文件（文件名可能为空），content 为文件的一段内容，
+/// finish 为 true 时结束当前文件并按 hash 校验（空文件的 hash 为空）；
+/// 发送完所有文件后发送 end 为 true 的消息，未收到 end 时视为上传中断，原子上传的文件不会提交
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct UploadChunk {
+    #[prost(string, tag = "1")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "2")]
+    pub expires_at: u64,
+    #[prost(bytes = "vec", tag = "3")]
+    pub content: ::prost::alloc::vec::Vec<u8>,
+    #[prost(string, tag = "4")]
+    pub hash: ::prost::alloc::string::String,
+    #[prost(bool, tag = "5")]
+    pub atomic: bool,
+    #[prost(bool, tag = "6")]
+    pub end: bool,
+    #[prost(bool, tag = "7")]
+    pub finish: bool,
+    #[prost(bool, tag = "8")]
+    pub start: bool,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -46,11 +73,14 @@ pub struct ExistsResponse {
     #[prost(bool, tag = "1")]
     pub exists: bool,
 }
+/// hash 为空时按文件名查找
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct DownloadRequest {
     #[prost(string, tag = "1")]
     pub hash: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -59,6 +89,76 @@ pub struct DownloadResponse {
     pub name: ::prost::alloc::string::String,
     #[prost(bytes = "vec", tag = "2")]
     pub content: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint64, tag = "3")]
+    pub expires_at: u64,
+    #[prost(string, tag = "4")]
+    pub hash: ::prost::alloc::string::String,
+}
+/// 只读取元数据，不读取内容
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StatRequest {
+    #[prost(string, tag = "1")]
+    pub hash: ::prost::alloc::string::String,
+}
+/// created_at 和 expires_at 为秒级时间戳，expires_at 为 0 表示永不过期
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StatResponse {
+    #[prost(string, tag = "1")]
+    pub hash: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "3")]
+    pub size: u64,
+    #[prost(uint64, tag = "4")]
+    pub created_at: u64,
+    #[prost(uint64, tag = "5")]
+    pub expires_at: u64,
+}
+/// hash 为空时删除文件名的引用
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteRequest {
+    #[prost(string, tag = "1")]
+    pub hash: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub name: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DeleteResponse {
+    #[prost(bool, tag = "1")]
+    pub deleted: bool,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CheckpointRequest {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CheckpointResponse {
+    #[prost(string, tag = "1")]
+    pub path: ::prost::alloc::string::String,
+}
+/// size 为准备上传的字节数
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CapacityRequest {
+    #[prost(uint64, tag = "1")]
+    pub size: u64,
+}
+/// 不限制容量时 total 为 0
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CapacityResponse {
+    #[prost(uint64, tag = "1")]
+    pub total: u64,
+    #[prost(uint64, tag = "2")]
+    pub used: u64,
+    #[prost(uint64, tag = "3")]
+    pub available: u64,
+    #[prost(bool, tag = "4")]
+    pub full: bool,
 }
 /// Generated client implementations.
 pub mod internal_files_client {
@@ -148,6 +248,27 @@ pub mod internal_files_client {
             );
             self.inner.unary(request.into_request(), path, codec).await
         }
+        pub async fn upload_stream(
+            &mut self,
+            request: impl tonic::IntoStreamingRequest<Message = super::UploadChunk>,
+        ) -> Result<tonic::Response<super::UploadResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/internalfiles.InternalFiles/upload_stream",
+            );
+            self.inner
+                .client_streaming(request.into_streaming_request(), path, codec)
+                .await
+        }
         pub async fn exists(
             &mut self,
             request: impl tonic::IntoRequest<super::ExistsRequest>,
@@ -186,6 +307,82 @@ pub mod internal_files_client {
             );
             self.inner.unary(request.into_request(), path, codec).await
         }
+        pub async fn stat(
+            &mut self,
+            request: impl tonic::IntoRequest<super::StatRequest>,
+        ) -> Result<tonic::Response<super::StatResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/internalfiles.InternalFiles/stat",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        pub async fn delete(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DeleteRequest>,
+        ) -> Result<tonic::Response<super::DeleteResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/internalfiles.InternalFiles/delete",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        pub async fn checkpoint(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CheckpointRequest>,
+        ) -> Result<tonic::Response<super::CheckpointResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/internalfiles.InternalFiles/checkpoint",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
+        pub async fn capacity(
+            &mut self,
+            request: impl tonic::IntoRequest<super::CapacityRequest>,
+        ) -> Result<tonic::Response<super::CapacityResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/internalfiles.InternalFiles/capacity",
+            );
+            self.inner.unary(request.into_request(), path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -199,6 +396,10 @@ pub mod internal_files_server {
             &self,
             request: tonic::Request<super::UploadRequest>,
         ) -> Result<tonic::Response<super::UploadResponse>, tonic::Status>;
+        async fn upload_stream(
+            &self,
+            request: tonic::Request<tonic::Streaming<super::UploadChunk>>,
+        ) -> Result<tonic::Response<super::UploadResponse>, tonic::Status>;
         async fn exists(
             &self,
             request: tonic::Request<super::ExistsRequest>,
@@ -207,6 +408,22 @@ pub mod internal_files_server {
             &self,
             request: tonic::Request<super::DownloadRequest>,
         ) -> Result<tonic::Response<super::DownloadResponse>, tonic::Status>;
+        async fn stat(
+            &self,
+            request: tonic::Request<super::StatRequest>,
+        ) -> Result<tonic::Response<super::StatResponse>, tonic::Status>;
+        async fn delete(
+            &self,
+            request: tonic::Request<super::DeleteRequest>,
+        ) -> Result<tonic::Response<super::DeleteResponse>, tonic::Status>;
+        async fn checkpoint(
+            &self,
+            request: tonic::Request<super::CheckpointRequest>,
+        ) -> Result<tonic::Response<super::CheckpointResponse>, tonic::Status>;
+        async fn capacity(
+            &self,
+            request: tonic::Request<super::CapacityRequest>,
+        ) -> Result<tonic::Response<super::CapacityResponse>, tonic::Status>;
     }
     #[derive(Debug)]
     pub struct InternalFilesServer<T: InternalFiles> {
@@ -305,6 +522,46 @@ pub mod internal_files_server {
                     };
                     Box::pin(fut)
                 }
+                "/internalfiles.InternalFiles/upload_stream" => {
+                    #[allow(non_camel_case_types)]
+                    struct upload_streamSvc<T: InternalFiles>(pub Arc<T>);
+                    impl<
+                        T: InternalFiles,
+                    > tonic::server::ClientStreamingService<super::UploadChunk>
+                    for upload_streamSvc<T> {
+                        type Response = super::UploadResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<tonic::Streaming<super::UploadChunk>>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move {
+                                (*inner).upload_stream(request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = upload_streamSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            );
+                        let res = grpc.client_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 "/internalfiles.InternalFiles/exists" => {
                     #[allow(non_camel_case_types)]
                     struct existsSvc<T: InternalFiles>(pub Arc<T>);
@@ -381,6 +638,157 @@ pub mod internal_files_server {
                     };
                     Box::pin(fut)
                 }
+                "/internalfiles.InternalFiles/stat" => {
+                    #[allow(non_camel_case_types)]
+                    struct statSvc<T: InternalFiles>(pub Arc<T>);
+                    impl<
+                        T: InternalFiles,
+                    > tonic::server::UnaryService<super::StatRequest> for statSvc<T> {
+                        type Response = super::StatResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::StatRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).stat(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = statSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/internalfiles.InternalFiles/delete" => {
+                    #[allow(non_camel_case_types)]
+                    struct deleteSvc<T: InternalFiles>(pub Arc<T>);
+                    impl<
+                        T: InternalFiles,
+                    > tonic::server::UnaryService<super::DeleteRequest>
+                    for deleteSvc<T> {
+                        type Response = super::DeleteResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DeleteRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).delete(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = deleteSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/internalfiles.InternalFiles/checkpoint" => {
+                    #[allow(non_camel_case_types)]
+                    struct checkpointSvc<T: InternalFiles>(pub Arc<T>);
+                    impl<
+                        T: InternalFiles,
+                    > tonic::server::UnaryService<super::CheckpointRequest>
+                    for checkpointSvc<T> {
+                        type Response = super::CheckpointResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CheckpointRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).checkpoint(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = checkpointSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/internalfiles.InternalFiles/capacity" => {
+                    #[allow(non_camel_case_types)]
+                    struct capacitySvc<T: InternalFiles>(pub Arc<T>);
+                    impl<
+                        T: InternalFiles,
+                    > tonic::server::UnaryService<super::CapacityRequest>
+                    for capacitySvc<T> {
+                        type Response = super::CapacityResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::CapacityRequest>,
+                        ) -> Self::Future {
+                            let inner = self.0.clone();
+                            let fut = async move { (*inner).capacity(request).await };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = capacitySvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         Ok(
@@ -0,0 +1,71 @@
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct FileManifest {
+    #[prost(string, tag = "1")]
+    pub file_hash: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub file_name: ::prost::alloc::string::String,
+    #[prost(uint64, tag = "3")]
+    pub size: u64,
+    #[prost(uint64, tag = "4")]
+    pub chunk_size: u64,
+    #[prost(string, repeated, tag = "5")]
+    pub chunks: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(string, repeated, tag = "6")]
+    pub names: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(uint64, tag = "7")]
+    pub created_at: u64,
+    #[prost(enumeration = "Codec", tag = "8")]
+    pub codec: i32,
+    #[prost(string, optional, tag = "9")]
+    pub key_id: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(string, optional, tag = "10")]
+    pub wrapped_key: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint64, optional, tag = "11")]
+    pub expires_at: ::core::option::Option<u64>,
+    #[prost(uint64, tag = "12")]
+    pub accessed_at: u64,
+    #[prost(bool, tag = "13")]
+    pub cold: bool,
+}
+/// 未完成的可续传上传，manifest 的 size 为已接收的字节数，
+/// 不足一个分块的剩余内容保存在 tail 中，开启加密时为密文
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PartialUpload {
+    #[prost(message, optional, tag = "1")]
+    pub manifest: ::core::option::Option<FileManifest>,
+    #[prost(uint64, tag = "2")]
+    pub length: u64,
+    #[prost(bytes = "vec", tag = "3")]
+    pub tail: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum Codec {
+    None = 0,
+    Zstd = 1,
+    Lz4 = 2,
+}
+impl Codec {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            Codec::None => "CODEC_NONE",
+            Codec::Zstd => "CODEC_ZSTD",
+            Codec::Lz4 => "CODEC_LZ4",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "CODEC_NONE" => Some(Self::None),
+            "CODEC_ZSTD" => Some(Self::Zstd),
+            "CODEC_LZ4" => Some(Self::Lz4),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use crate::config::{configs::Engine, CONFIG};
+use crate::storage::{storage_conn, DB};
+use anyhow::{anyhow, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
+use tracing::info;
+
+// 在线创建快照，节点照常处理读写，返回快照目录
+pub async fn checkpoint() -> Result<PathBuf> {
+    let start = Instant::now();
+    let dir = Path::new(&CONFIG.backup.dir);
+    fs::create_dir_all(dir)?;
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+    let path = dir.join(format!("checkpoint-{}", millis));
+
+    let db = DB.get_or_init(storage_conn).await;
+    db.checkpoint(path.clone()).await?;
+    let duration = start.elapsed();
+    info!("checkpoint {} cost {:?}", path.display(), duration);
+    Ok(path)
+}
+
+// 需在节点停止时执行，原数据目录重命名保留，再从快照复制出新的数据目录
+pub fn restore(checkpoint: &str) -> Result<()> {
+    if CONFIG.database.engine != Engine::Rocksdb {
+        return Err(anyhow!(
+            "restore is not supported by engine {:?}",
+            CONFIG.database.engine
+        ));
+    }
+    let checkpoint = Path::new(checkpoint);
+    if !checkpoint.join("CURRENT").is_file() {
+        return Err(anyhow!("{} is not a checkpoint", checkpoint.display()));
+    }
+
+    let url = Path::new(&CONFIG.database.url);
+    if url.exists() {
+        let secs = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let backup = PathBuf::from(format!("{}.{}.bak", CONFIG.database.url, secs));
+        fs::rename(url, &backup)?;
+        info!("move {} to {}", url.display(), backup.display());
+    }
+    copy_dir(checkpoint, url)?;
+    info!("restore {} from {}", url.display(), checkpoint.display());
+    Ok(())
+}
+
+fn copy_dir(from: &Path, to: &Path) -> Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let target = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &target)?;
+        } else {
+            fs::copy(entry.path(), target)?;
+        }
+    }
+    Ok(())
+}
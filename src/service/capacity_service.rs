@@ -0,0 +1,48 @@
+use crate::config::CONFIG;
+use crate::storage::{storage_conn, Capacity, DB};
+use anyhow::Result;
+use std::fmt;
+
+// 写入后已用空间将超过高水位，或剩余空间不足
+#[derive(Debug)]
+pub struct InsufficientStorageError {
+    pub used: u64,
+    pub total: u64,
+    pub size: u64,
+}
+
+impl fmt::Display for InsufficientStorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "insufficient storage, used {} of {} bytes, upload {} bytes",
+            self.used, self.total, self.size
+        )
+    }
+}
+
+impl std::error::Error for InsufficientStorageError {}
+
+pub async fn capacity() -> Result<Option<Capacity>> {
+    let db = DB.get_or_init(storage_conn).await;
+    db.capacity().await
+}
+
+// 再写入 size 字节后是否超过高水位
+pub fn is_full(capacity: &Capacity, size: u64) -> bool {
+    let watermark = capacity.total as f64 * CONFIG.capacity.high_watermark;
+    size > capacity.available || (capacity.used + size) as f64 > watermark
+}
+
+// 上传前检查本节点是否还能写入 size 字节
+pub async fn check(size: u64) -> Result<()> {
+    match capacity().await? {
+        Some(capacity) if is_full(&capacity, size) => Err(InsufficientStorageError {
+            used: capacity.used,
+            total: capacity.total,
+            size,
+        }
+        .into()),
+        _ => Ok(()),
+    }
+}
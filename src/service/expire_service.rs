@@ -0,0 +1,83 @@
+use crate::config::CONFIG;
+use crate::model::file_model::now_secs;
+use crate::service::file_service;
+use crate::storage::{storage_conn, Column, DB};
+use anyhow::Result;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+use tracing::{info, warn};
+
+// 定期删除已过期的文件，过期时间随副本一起同步，各节点各自清理本节点上的副本
+// 同时删除长时间未继续上传的可续传上传
+pub async fn run() {
+    let interval = Duration::from_secs(CONFIG.expire.interval_secs);
+    loop {
+        match sweep().await {
+            Ok(deleted) => info!("sweep finished, deleted: {}", deleted),
+            Err(err) => warn!("sweep failed: {:?}", err),
+        }
+        match sweep_uploads().await {
+            Ok(deleted) => info!("sweep uploads finished, deleted: {}", deleted),
+            Err(err) => warn!("sweep uploads failed: {:?}", err),
+        }
+        time::sleep(interval).await;
+    }
+}
+
+async fn sweep() -> Result<usize> {
+    let start = Instant::now();
+    let db = DB.get_or_init(storage_conn).await;
+    let mut deleted = 0;
+
+    let mut cursor: Option<String> = None;
+    loop {
+        let items = db.scan(Column::Meta, "", cursor.as_deref(), 100).await?;
+        if items.is_empty() {
+            break;
+        }
+        cursor = items.last().map(|(key, _)| key.clone());
+
+        for (hash, data) in items {
+            match file_service::decode_manifest(&data) {
+                Ok(manifest) if manifest.expired() => {
+                    if file_service::delete(&hash).await? {
+                        deleted += 1;
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => warn!("decode {} failed: {:?}", hash, err),
+            }
+        }
+    }
+    info!("sweep cost {:?}", start.elapsed());
+    Ok(deleted)
+}
+
+async fn sweep_uploads() -> Result<usize> {
+    let db = DB.get_or_init(storage_conn).await;
+    let mut deleted = 0;
+
+    let mut cursor: Option<String> = None;
+    loop {
+        let items = db.scan(Column::Uploads, "", cursor.as_deref(), 100).await?;
+        if items.is_empty() {
+            break;
+        }
+        cursor = items.last().map(|(key, _)| key.clone());
+
+        for (id, _) in items {
+            match file_service::find_upload(&id).await {
+                Ok(Some(upload)) if upload.upload_expires_at() <= now_secs() => {
+                    match file_service::delete_upload(&id).await {
+                        Ok(true) => deleted += 1,
+                        Ok(false) => {}
+                        Err(err) => warn!("delete upload {} failed: {:?}", id, err),
+                    }
+                }
+                Ok(_) => {}
+                Err(err) => warn!("decode upload {} failed: {:?}", id, err),
+            }
+        }
+    }
+    Ok(deleted)
+}
@@ -1,33 +1,1884 @@
-use crate::model::file_model::FileInfo;
-use crate::storage::{rocksdb_conn, Storage, DB};
-use anyhow::{Ok, Result};
-use tokio::time::Instant;
-use tracing::info;
+use crate::config::CONFIG;
+use crate::model::file_model::{
+    now_secs, records, CacheHead, FileHead, FileInfo, FileList, FileManifest, FileStat,
+};
+use crate::service::{capacity_service, key_service};
+use crate::storage::{cold_conn, storage_conn, AsyncStorage, BatchOp, Column, COLD, DB};
+use crate::util::compress::{self, Codec};
+use crate::util::crypto::{self, KEY_LEN};
+use crate::util::lru::LruCache;
+use anyhow::{anyhow, Ok, Result};
+use data_encoding::HEXUPPER;
+use once_cell::sync::Lazy;
+use prost::Message;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::{Mutex, OwnedMutexGuard};
+use tokio::{task, time::Instant};
+use tracing::{info, warn};
 
-pub async fn save(hash: &str, data: Vec<u8>) -> Result<()> {
+// 读取到的内容与 hash 不一致，或分块缺失、无法解密解压
+#[derive(Debug)]
+pub struct CorruptedError {
+    pub hash: String,
+    pub reason: String,
+}
+
+impl fmt::Display for CorruptedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "file {} is corrupted: {}", self.hash, self.reason)
+    }
+}
+
+impl std::error::Error for CorruptedError {}
+
+// 分块缺失或无法解密解压，与读取存储失败不同，说明文件已损坏
+#[derive(Debug)]
+struct BrokenChunkError {
+    key: String,
+    reason: String,
+}
+
+impl fmt::Display for BrokenChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "chunk {} is broken: {}", self.key, self.reason)
+    }
+}
+
+impl std::error::Error for BrokenChunkError {}
+
+// 上传的文件超过配置的最大大小
+#[derive(Debug)]
+pub struct FileTooLargeError {
+    pub name: String,
+    pub limit: u64,
+}
+
+impl fmt::Display for FileTooLargeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "file {} exceeds the maximum size of {} bytes",
+            self.name, self.limit
+        )
+    }
+}
+
+impl std::error::Error for FileTooLargeError {}
+
+// 记录头：魔数和格式版本号，旧版本的 JSON 记录以 '{' 开头
+const RECORD_MAGIC: u8 = 0xF5;
+const RECORD_VERSION: u8 = 1;
+
+// 读取时间的最大记录精度（秒）
+const ACCESS_PRECISION_SECS: u64 = 60 * 60;
+
+// 热点文件的读缓存，内容按 hash 寻址不会过时，只在文件被删除或清单变化时淘汰
+static CACHE: Lazy<std::sync::Mutex<LruCache<Arc<CachedFile>>>> =
+    Lazy::new(|| std::sync::Mutex::new(LruCache::new(CONFIG.cache.size)));
+// 每次淘汰缓存时加一
+static CACHE_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+struct CachedFile {
+    file_info: FileInfo,
+    // 最近一次记录读取时间的时间（秒级时间戳）
+    touched_at: AtomicU64,
+}
+
+// 文件名引用的增减按内容 hash 和文件名分段加锁，避免并发上传、删除时丢失引用，
+// 不同文件的上传、删除可以并发执行
+const REF_STRIPES: usize = 64;
+static REFS: Lazy<Vec<Arc<Mutex<()>>>> =
+    Lazy::new(|| (0..REF_STRIPES).map(|_| Arc::new(Mutex::new(()))).collect());
+
+// 按固定顺序锁住 key 所在的分段，避免死锁
+async fn lock_refs<'a>(keys: impl IntoIterator<Item = &'a str>) -> Vec<OwnedMutexGuard<()>> {
+    let mut stripes: Vec<usize> = keys
+        .into_iter()
+        .map(|key| {
+            let mut hasher = DefaultHasher::new();
+            key.hash(&mut hasher);
+            hasher.finish() as usize % REF_STRIPES
+        })
+        .collect();
+    stripes.sort_unstable();
+    stripes.dedup();
+    let mut guards = Vec::with_capacity(stripes.len());
+    for stripe in stripes {
+        guards.push(REFS[stripe].clone().lock_owned().await);
+    }
+    guards
+}
+
+// 锁住内容 hash、文件名及文件名当前指向的内容，加锁期间文件名指向了其他内容时重新加锁
+async fn lock_names(
+    db: &AsyncStorage,
+    hashes: &[&str],
+    names: &[&str],
+) -> Result<Vec<OwnedMutexGuard<()>>> {
+    let mut keys: Vec<String> = hashes.iter().map(|hash| hash.to_string()).collect();
+    keys.extend(names.iter().map(|name| name_key(name)));
+    loop {
+        let guards = lock_refs(keys.iter().map(String::as_str)).await;
+        let txn = Txn::new(db);
+        let mut stale = false;
+        for name in names {
+            if let Some(previous) = txn.resolve(name).await? {
+                if !keys.contains(&previous) {
+                    keys.push(previous);
+                    stale = true;
+                }
+            }
+        }
+        if !stale {
+            return Ok(guards);
+        }
+    }
+}
+
+pub async fn save(file_info: &FileInfo) -> Result<()> {
     let start = Instant::now();
-    let db = DB.get_or_init(rocksdb_conn).await;
-    db.put(hash, data)?;
+    let db = DB.get_or_init(storage_conn).await;
+    if file_info.file_name.is_empty() {
+        return Err(anyhow!("file name is empty"));
+    }
+    // 相同内容只保存一份，再次上传只增加文件名引用；put 会覆盖已有的清单，
+    // 需与文件名引用在同一批次中检查，避免并发上传相同内容时覆盖对方的引用
+    let _refs = lock_names(
+        db,
+        &[file_info.file_hash.as_str()],
+        &[file_info.file_name.as_str()],
+    )
+    .await?;
+    let mut txn = Txn::new(db);
+    if !txn.exists(Column::Meta, &file_info.file_hash).await? {
+        stage_content(&mut txn, file_info).await?;
+    }
+    add_ref(
+        &mut txn,
+        file_info.file_hash.as_str(),
+        file_info.file_name.as_str(),
+        file_info.expires_at,
+    )
+    .await?;
+    txn.commit().await?;
     let duration = start.elapsed();
     info!("save cost {:?}", duration);
     Ok(())
 }
 
+async fn stage_content(txn: &mut Txn<'_>, file_info: &FileInfo) -> Result<()> {
+    let chunk_size = CONFIG.database.chunk_size;
+    let sample = &file_info.content[..file_info.content.len().min(chunk_size)];
+    let codec = choose_codec(sample).await?;
+    let mut manifest = FileManifest::new(
+        file_info.file_hash.clone(),
+        file_info.file_name.clone(),
+        file_info.size,
+        chunk_size,
+        Vec::new(),
+        codec,
+    );
+    manifest.expires_at = file_info.expires_at;
+    let data_key = seal_manifest(&mut manifest)?;
+
+    let transform = ChunkTransform { codec, data_key };
+    for (index, chunk) in file_info.content.chunks(chunk_size).enumerate() {
+        let key = chunk_key(&file_info.file_hash, index);
+        let data = transform.encode(&key, chunk.to_vec()).await?;
+        txn.put(Column::Content, &key, data);
+        manifest.chunks.push(key);
+    }
+
+    // 分块全部写入后再写清单，清单存在即代表文件完整
+    txn.put(
+        Column::Meta,
+        &file_info.file_hash,
+        encode_manifest(&manifest)?,
+    );
+    Ok(())
+}
+
+// 配置了主密钥时为文件生成数据密钥，包装后记录在清单中
+fn seal_manifest(manifest: &mut FileManifest) -> Result<Option<[u8; KEY_LEN]>> {
+    let master_key = match key_service::master_key()? {
+        Some(master_key) => master_key,
+        None => return Ok(None),
+    };
+    let key = crypto::random_key()?;
+    manifest.key_id = Some(master_key.id.clone());
+    manifest.wrapped_key = Some(master_key.wrap(&key)?);
+    Ok(Some(key))
+}
+
+// 流式上传的文件：边接收边计算 hash，内容按分块直接写入存储，分块以随机的上传 id 命名，
+// 内存中最多保留一个分块
+pub struct Upload {
+    db: &'static AsyncStorage,
+    id: String,
+    manifest: FileManifest,
+    data_key: Option<[u8; KEY_LEN]>,
+    // 写入首个分块时按其压缩效果确定
+    transform: Option<ChunkTransform>,
+    // 恢复的可续传上传没有之前的 hash 状态，完成时重新读取分块计算
+    hasher: Option<crypto::Sha256>,
+    buffer: Vec<u8>,
+}
+
+impl Upload {
+    pub async fn new(name: &str, expires_at: Option<u64>) -> Result<Self> {
+        let db = DB.get_or_init(storage_conn).await;
+        let mut manifest = FileManifest::new(
+            String::new(),
+            name.to_string(),
+            0,
+            CONFIG.database.chunk_size,
+            Vec::new(),
+            Codec::None,
+        );
+        manifest.expires_at = expires_at;
+        let data_key = seal_manifest(&mut manifest)?;
+        Ok(Upload {
+            db,
+            id: HEXUPPER.encode(&crypto::random_key()?),
+            manifest,
+            data_key,
+            transform: None,
+            hasher: Some(crypto::Sha256::new()),
+            buffer: Vec::new(),
+        })
+    }
+
+    // 出错时调用方需调用 abort 删除已写入的分块
+    pub async fn write(&mut self, data: &[u8]) -> Result<()> {
+        let limit = CONFIG.upload.max_file_size;
+        if (self.manifest.size + data.len()) as u64 > limit {
+            return Err(FileTooLargeError {
+                name: self.manifest.file_name.clone(),
+                limit,
+            }
+            .into());
+        }
+        self.manifest.size += data.len();
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(data);
+        }
+        self.buffer.extend_from_slice(data);
+        // 分块写入成功后才从缓冲区移除，出错时已接收的内容仍完整保留
+        let chunk_size = self.manifest.chunk_size;
+        while self.buffer.len() >= chunk_size {
+            let chunk = self.buffer[..chunk_size].to_vec();
+            self.write_chunk(chunk).await?;
+            self.buffer.drain(..chunk_size);
+        }
+        Ok(())
+    }
+
+    async fn write_chunk(&mut self, chunk: Vec<u8>) -> Result<()> {
+        let transform = match self.transform {
+            Some(transform) => transform,
+            None => {
+                let codec = choose_codec(&chunk).await?;
+                self.manifest.codec = codec;
+                let transform = ChunkTransform {
+                    codec,
+                    data_key: self.data_key,
+                };
+                self.transform = Some(transform);
+                transform
+            }
+        };
+        let key = chunk_key(&self.id, self.manifest.chunks.len());
+        let data = transform.encode(&key, chunk).await?;
+        // 文件大小在接收完之前可能未知（如分块传输），写入每个分块前检查容量
+        capacity_service::check(data.len() as u64).await?;
+        self.db.put(Column::Content, &key, data).await?;
+        self.manifest.chunks.push(key);
+        Ok(())
+    }
+
+    pub async fn finish(mut self) -> Result<StagedFile> {
+        let sealed = self.seal().await;
+        if sealed.is_err() {
+            self.abort().await;
+        }
+        sealed
+    }
+
+    // 写入剩余内容并计算 hash，失败时已接收的内容仍完整保留，可续传上传可以保存进度后重试
+    async fn seal(&mut self) -> Result<StagedFile> {
+        if !self.buffer.is_empty() {
+            let chunk = self.buffer.clone();
+            self.write_chunk(chunk).await?;
+            self.buffer.clear();
+        }
+        let mut manifest = self.manifest.clone();
+        manifest.file_hash = match self.hasher.take() {
+            Some(hasher) => hasher.finish(),
+            None => self.digest().await?,
+        };
+        Ok(StagedFile { manifest })
+    }
+
+    async fn digest(&self) -> Result<String> {
+        let transform = ChunkTransform {
+            codec: self.manifest.codec,
+            data_key: self.data_key,
+        };
+        let mut hasher = crypto::Sha256::new();
+        for key in self.manifest.chunks.iter() {
+            hasher.update(&load_chunk(key, false, transform).await?);
+        }
+        Ok(hasher.finish())
+    }
+
+    // 恢复保存的可续传上传
+    async fn resume(id: &str) -> Result<Option<(Self, u64)>> {
+        let db = DB.get_or_init(storage_conn).await;
+        let data = match db.get(Column::Uploads, id).await? {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let record = records::PartialUpload::decode(record_body(&data)?)?;
+        let manifest: FileManifest = record
+            .manifest
+            .ok_or_else(|| anyhow!("upload {} has no manifest", id))?
+            .try_into()?;
+        let data_key = ChunkTransform::from_manifest(&manifest)?.data_key;
+        let transform = (!manifest.chunks.is_empty()).then_some(ChunkTransform {
+            codec: manifest.codec,
+            data_key,
+        });
+        let buffer = match data_key {
+            Some(data_key) if !record.tail.is_empty() => {
+                crypto::decrypt(&data_key, id.as_bytes(), record.tail)?
+            }
+            _ => record.tail,
+        };
+        let upload = Upload {
+            db,
+            id: id.to_string(),
+            manifest,
+            data_key,
+            transform,
+            hasher: None,
+            buffer,
+        };
+        Ok(Some((upload, record.length)))
+    }
+
+    // 保存可续传上传的进度，未满一个分块的内容随进度一起保存
+    async fn suspend(&self, length: u64) -> Result<()> {
+        let tail = match self.data_key {
+            Some(data_key) if !self.buffer.is_empty() => {
+                crypto::encrypt(&data_key, self.id.as_bytes(), self.buffer.clone())?
+            }
+            _ => self.buffer.clone(),
+        };
+        let record = records::PartialUpload {
+            manifest: Some(records::FileManifest::from(&self.manifest)),
+            length,
+            tail,
+        };
+        self.db
+            .put(Column::Uploads, &self.id, encode_record(&record)?)
+            .await
+    }
+
+    fn progress(&self, length: u64) -> ResumableUpload {
+        ResumableUpload {
+            id: self.id.clone(),
+            name: self.manifest.file_name.clone(),
+            offset: self.manifest.size as u64,
+            length,
+            updated_at: self.manifest.accessed_at,
+            expires_at: self.manifest.expires_at,
+            hash: None,
+        }
+    }
+
+    pub async fn abort(self) {
+        discard_chunks(self.db, &self.manifest.chunks).await;
+    }
+}
+
+// 内容已写入存储、尚未提交清单和文件名引用的文件
+pub struct StagedFile {
+    manifest: FileManifest,
+}
+
+impl StagedFile {
+    pub fn hash(&self) -> &str {
+        &self.manifest.file_hash
+    }
+
+    pub fn name(&self) -> &str {
+        &self.manifest.file_name
+    }
+
+    pub fn size(&self) -> usize {
+        self.manifest.size
+    }
+
+    pub async fn discard(self) {
+        let db = DB.get_or_init(storage_conn).await;
+        discard_chunks(db, &self.manifest.chunks).await;
+    }
+}
+
+async fn discard_chunks(db: &AsyncStorage, chunks: &[String]) {
+    for key in chunks {
+        if let Err(err) = db.delete(Column::Content, key).await {
+            warn!("delete chunk {} failed: {:?}", key, err);
+        }
+    }
+}
+
+// 提交流式上传的文件，失败时删除其分块
+pub async fn save_staged(file: &StagedFile) -> Result<()> {
+    commit_staged(std::slice::from_ref(file), None).await
+}
+
+// 所有文件在一个批次中提交，要么全部保存，要么全部不保存
+pub async fn save_staged_all(files: &[StagedFile]) -> Result<()> {
+    let db = DB.get_or_init(storage_conn).await;
+    if !db.atomic_write() {
+        discard_staged(db, files).await;
+        return Err(anyhow!(
+            "atomic upload is not supported by engine {:?}",
+            CONFIG.database.engine
+        ));
+    }
+    commit_staged(files, None).await
+}
+
+// 写入清单和文件名引用，相同内容已存在时删除本次写入的分块，只增加文件名引用
+// finished 为已完成的可续传上传，其进度在同一批次中删除；提交失败时保留其进度和分块，客户端可重试
+async fn commit_staged(files: &[StagedFile], finished: Option<&str>) -> Result<()> {
+    let start = Instant::now();
+    let db = DB.get_or_init(storage_conn).await;
+    let result = async {
+        let hashes: Vec<&str> = files.iter().map(|f| f.hash()).collect();
+        let names: Vec<&str> = files.iter().map(|f| f.name()).collect();
+        let _refs = lock_names(db, &hashes, &names).await?;
+        let mut txn = Txn::new(db);
+        // 先删除进度，不支持原子写入的引擎中途失败时只会遗留分块，不会在清理进度时删除已提交文件的分块
+        if let Some(id) = finished {
+            txn.delete(Column::Uploads, id);
+        }
+        for file in files {
+            let manifest = &file.manifest;
+            if manifest.file_name.is_empty() {
+                return Err(anyhow!("file name is empty"));
+            }
+            let hash = manifest.file_hash.as_str();
+            if txn.exists(Column::Meta, hash).await? {
+                // 重试提交时清单可能已由之前中途失败的提交写入，此时分块已被引用
+                if txn.manifest(hash).await?.chunks != manifest.chunks {
+                    for key in manifest.chunks.iter() {
+                        txn.delete(Column::Content, key);
+                    }
+                }
+            } else {
+                txn.put(Column::Meta, hash, encode_manifest(manifest)?);
+            }
+            add_ref(&mut txn, hash, &manifest.file_name, manifest.expires_at).await?;
+        }
+        txn.commit().await
+    }
+    .await;
+    if let Err(err) = result {
+        if finished.is_none() {
+            discard_staged(db, files).await;
+        }
+        return Err(err);
+    }
+    let duration = start.elapsed();
+    info!("save {} staged files cost {:?}", files.len(), duration);
+    Ok(())
+}
+
+// 可续传上传的请求无法处理
+#[derive(Debug)]
+pub enum UploadError {
+    NotFound,
+    // 请求的 offset 与已接收的字节数不一致
+    Offset(u64),
+    // 同一上传正在处理其他请求
+    Locked,
+    // 内容超过创建时声明的长度
+    Length(u64),
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadError::NotFound => write!(f, "upload not found"),
+            UploadError::Offset(offset) => write!(f, "upload offset is {}", offset),
+            UploadError::Locked => write!(f, "upload is locked by another request"),
+            UploadError::Length(length) => write!(f, "upload length is {}", length),
+        }
+    }
+}
+
+impl std::error::Error for UploadError {}
+
+// 可续传上传的进度
+#[derive(Debug)]
+pub struct ResumableUpload {
+    pub id: String,
+    pub name: String,
+    // 已接收的字节数
+    pub offset: u64,
+    pub length: u64,
+    // 最近一次接收内容的时间（秒级时间戳）
+    pub updated_at: u64,
+    // 上传完成后文件的过期时间
+    pub expires_at: Option<u64>,
+    // 上传完成后文件的 hash
+    pub hash: Option<String>,
+}
+
+impl ResumableUpload {
+    // 超过该时间未继续上传时删除
+    pub fn upload_expires_at(&self) -> u64 {
+        self.updated_at + CONFIG.tus.expire_secs
+    }
+}
+
+// 正在处理请求的可续传上传，同一上传同时只处理一个请求
+static ACTIVE_UPLOADS: Lazy<std::sync::Mutex<HashSet<String>>> =
+    Lazy::new(|| std::sync::Mutex::new(HashSet::new()));
+
+struct UploadLock(String);
+
+impl UploadLock {
+    fn acquire(id: &str) -> Result<Self> {
+        let mut active = ACTIVE_UPLOADS.lock().unwrap_or_else(|e| e.into_inner());
+        if !active.insert(id.to_string()) {
+            return Err(UploadError::Locked.into());
+        }
+        Ok(UploadLock(id.to_string()))
+    }
+}
+
+impl Drop for UploadLock {
+    fn drop(&mut self) {
+        let mut active = ACTIVE_UPLOADS.lock().unwrap_or_else(|e| e.into_inner());
+        active.remove(&self.0);
+    }
+}
+
+pub async fn create_upload(
+    name: &str,
+    length: u64,
+    expires_at: Option<u64>,
+) -> Result<ResumableUpload> {
+    if name.is_empty() {
+        return Err(anyhow!("file name is empty"));
+    }
+    let limit = CONFIG.upload.max_file_size;
+    if length > limit {
+        return Err(FileTooLargeError {
+            name: name.to_string(),
+            limit,
+        }
+        .into());
+    }
+    let upload = Upload::new(name, expires_at).await?;
+    let mut progress = upload.progress(length);
+    // 空文件不会再收到 PATCH 请求，创建时直接保存
+    if length == 0 {
+        let file = upload.finish().await?;
+        save_staged(&file).await?;
+        info!(
+            "create upload {} of empty file {}",
+            progress.id,
+            file.hash()
+        );
+        progress.hash = Some(file.hash().to_string());
+        return Ok(progress);
+    }
+    upload.suspend(length).await?;
+    info!("create upload {} of {} bytes", progress.id, length);
+    Ok(progress)
+}
+
+pub async fn find_upload(id: &str) -> Result<Option<ResumableUpload>> {
+    let upload = Upload::resume(id).await?;
+    Ok(upload.map(|(upload, length)| upload.progress(length)))
+}
+
+// 从 offset 处追加内容，中途出错时保存已接收的内容，客户端可从新的 offset 继续上传；
+// 全部接收后保存为按 hash 寻址的文件
+pub async fn append_upload(
+    id: &str,
+    offset: u64,
+    mut reader: impl AsyncRead + Unpin,
+) -> Result<ResumableUpload> {
+    let start = Instant::now();
+    let _lock = UploadLock::acquire(id)?;
+    let (mut upload, length) = Upload::resume(id).await?.ok_or(UploadError::NotFound)?;
+    if offset != upload.manifest.size as u64 {
+        return Err(UploadError::Offset(upload.manifest.size as u64).into());
+    }
+
+    let mut buffer = vec![0u8; upload.manifest.chunk_size];
+    let result: Result<()> = async {
+        loop {
+            let len = reader.read(&mut buffer).await?;
+            if len == 0 {
+                return Ok(());
+            }
+            if (upload.manifest.size + len) as u64 > length {
+                return Err(UploadError::Length(length).into());
+            }
+            upload.write(&buffer[..len]).await?;
+        }
+    }
+    .await;
+    upload.manifest.accessed_at = now_secs();
+    upload.suspend(length).await?;
+    result?;
+
+    let mut progress = upload.progress(length);
+    if progress.offset == length {
+        let sealed = upload.seal().await;
+        if sealed.is_err() {
+            upload.suspend(length).await?;
+        }
+        let file = sealed?;
+        let committed = commit_staged(std::slice::from_ref(&file), Some(id)).await;
+        if committed.is_err() {
+            // 不支持原子写入的引擎中途失败时进度可能已删除，重新保存以便客户端重试
+            upload.suspend(length).await?;
+        }
+        committed?;
+        progress.hash = Some(file.hash().to_string());
+    }
+    let duration = start.elapsed();
+    info!(
+        "append upload {} cost {:?}, offset: {}",
+        id, duration, progress.offset
+    );
+    Ok(progress)
+}
+
+// 删除未完成的上传及其已写入的分块
+pub async fn delete_upload(id: &str) -> Result<bool> {
+    let _lock = UploadLock::acquire(id)?;
+    let (upload, _) = match Upload::resume(id).await? {
+        Some(upload) => upload,
+        None => return Ok(false),
+    };
+    upload.db.delete(Column::Uploads, id).await?;
+    upload.abort().await;
+    info!("delete upload {}", id);
+    Ok(true)
+}
+
+// 删除未被清单引用的分块，非原子写入的引擎提交失败时清单可能已写入
+async fn discard_staged(db: &AsyncStorage, files: &[StagedFile]) {
+    for file in files {
+        let stored = db
+            .get(Column::Meta, file.hash())
+            .await
+            .ok()
+            .flatten()
+            .and_then(|data| decode_manifest(&data).ok());
+        if stored.is_some_and(|manifest| manifest.chunks == file.manifest.chunks) {
+            continue;
+        }
+        discard_chunks(db, &file.manifest.chunks).await;
+    }
+}
+
+// 文件名指向新内容时不释放旧内容：文件名索引在节点内全局唯一，不相关的上传可能使用相同的文件名，
+// 旧内容仍保留该文件名，可按 hash 访问，直到被按 hash 删除或过期
+async fn add_ref(txn: &mut Txn<'_>, hash: &str, name: &str, expires_at: Option<u64>) -> Result<()> {
+    let mut manifest = txn.manifest(hash).await?;
+    // 再次上传时保留较晚的过期时间，任一次上传未设置 TTL 则不再过期
+    let expires_at = match (manifest.expires_at, expires_at) {
+        (Some(current), Some(expires_at)) => Some(current.max(expires_at)),
+        _ => None,
+    };
+    let mut changed = manifest.expires_at != expires_at;
+    manifest.expires_at = expires_at;
+    if !manifest.names.iter().any(|n| n == name) {
+        manifest.names.push(name.to_string());
+        changed = true;
+    }
+    if changed {
+        txn.put(Column::Meta, hash, encode_manifest(&manifest)?);
+    }
+
+    txn.put(Column::Names, &name_key(name), hash.as_bytes().to_vec());
+    Ok(())
+}
+
+// 引用计数归零时删除内容，返回内容是否被删除
+async fn release_ref(txn: &mut Txn<'_>, hash: &str, name: &str) -> Result<bool> {
+    if !txn.exists(Column::Meta, hash).await? {
+        return Ok(false);
+    }
+    let mut manifest = txn.manifest(hash).await?;
+    manifest.names.retain(|n| n != name);
+    match manifest.names.first() {
+        Some(first) => {
+            manifest.file_name = first.clone();
+            txn.put(Column::Meta, hash, encode_manifest(&manifest)?);
+            Ok(false)
+        }
+        None => {
+            delete_content(txn, &manifest);
+            Ok(true)
+        }
+    }
+}
+
+// 返回文件名当前指向的内容 hash
+pub async fn resolve(name: &str) -> Result<Option<String>> {
+    let db = DB.get_or_init(storage_conn).await;
+    Txn::new(db).resolve(name).await
+}
+
+pub async fn find_by_name(name: &str) -> Result<FileInfo> {
+    let hash = resolve(name)
+        .await?
+        .ok_or_else(|| anyhow!("file {} not found", name))?;
+    let mut file_info = find(&hash).await?;
+    file_info.file_name = name.to_string();
+    Ok(file_info)
+}
+
 pub async fn find(hash: &str) -> Result<FileInfo> {
     let start = Instant::now();
-    let db = DB.get_or_init(rocksdb_conn).await;
-    let data = db.get(hash)?;
-    let file_info: FileInfo = serde_json::from_slice(&data.unwrap())?;
+    if let Some(cached) = cache_get(hash) {
+        if cached.file_info.expires_at.is_none_or(|t| t > now_secs()) {
+            if CONFIG.tier.enabled {
+                touch_cached(&cached).await;
+            }
+            let file_info = cached.file_info.clone();
+            info!("find cost {:?}, cache hit", start.elapsed());
+            return Ok(file_info);
+        }
+        cache_evict(hash);
+    }
+
+    let generation = CACHE_GENERATION.load(Ordering::SeqCst);
+    let file_info = load(hash, CONFIG.database.verify_on_read).await?;
+    cache_insert(&file_info, generation);
+    if CONFIG.tier.enabled {
+        if let Err(err) = touch(hash).await {
+            warn!("update access time of {} failed: {:?}", hash, err);
+        }
+    }
     let duration = start.elapsed();
     info!("find cost {:?}", duration);
     Ok(file_info)
 }
 
+// 命中缓存时同样记录读取时间，避免最常读取的文件被移入冷存储；
+// 按与 touch 相同的精度限制，精度内只有一个请求读取清单
+async fn touch_cached(cached: &CachedFile) {
+    let now = now_secs();
+    let touched_at = cached.touched_at.load(Ordering::Relaxed);
+    if now.saturating_sub(touched_at) < access_precision() {
+        return;
+    }
+    if cached
+        .touched_at
+        .compare_exchange(touched_at, now, Ordering::Relaxed, Ordering::Relaxed)
+        .is_err()
+    {
+        return;
+    }
+    let hash = cached.file_info.file_hash.as_str();
+    if let Err(err) = touch(hash).await {
+        warn!("update access time of {} failed: {:?}", hash, err);
+    }
+}
+
+// 只在锁内复制 Arc，复制内容在锁外进行
+fn cache_get(hash: &str) -> Option<Arc<CachedFile>> {
+    if CONFIG.cache.size == 0 {
+        return None;
+    }
+    cache().get(hash)
+}
+
+// 缓存里只有可重建的数据，锁中毒时继续使用
+fn cache() -> std::sync::MutexGuard<'static, LruCache<Arc<CachedFile>>> {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// 读取期间有文件被删除或修改时不写入缓存，避免缓存已删除的文件
+fn cache_insert(file_info: &FileInfo, generation: u64) {
+    if CONFIG.cache.size == 0 || file_info.size > CONFIG.cache.max_object_size {
+        return;
+    }
+    let size = file_info.content.len();
+    let cached = Arc::new(CachedFile {
+        file_info: file_info.clone(),
+        touched_at: AtomicU64::new(now_secs()),
+    });
+    let mut cache = cache();
+    if CACHE_GENERATION.load(Ordering::SeqCst) == generation {
+        cache.insert(&file_info.file_hash, cached, size);
+    }
+}
+
+fn cache_evict(hash: &str) {
+    let mut cache = cache();
+    CACHE_GENERATION.fetch_add(1, Ordering::SeqCst);
+    cache.remove(hash);
+}
+
+pub fn cache_stats() -> CacheHead {
+    let cache = cache();
+    CacheHead {
+        entries: cache.len(),
+        used: cache.used(),
+        limit: cache.limit(),
+        hits: cache.hits,
+        misses: cache.misses,
+    }
+}
+
+// 只读取清单，不读取内容
+// 文件不存在或已过期时返回 None
+pub async fn stat(hash: &str) -> Result<Option<FileStat>> {
+    let start = Instant::now();
+    let db = DB.get_or_init(storage_conn).await;
+    if !has_manifest(db, hash).await? {
+        return Ok(None);
+    }
+    let data = match db.get(Column::Meta, hash).await? {
+        Some(data) => data,
+        None => return Ok(None),
+    };
+    let manifest = decode_manifest(&data)?;
+    if manifest.expired() {
+        return Ok(None);
+    }
+    let duration = start.elapsed();
+    info!("stat cost {:?}", duration);
+    Ok(Some(FileStat::from(&manifest)))
+}
+
+// 无论是否开启读取校验都重新计算 hash，损坏的文件会被移入隔离区
+pub async fn verify(hash: &str) -> Result<()> {
+    load(hash, true).await?;
+    Ok(())
+}
+
+async fn load(hash: &str, verify: bool) -> Result<FileInfo> {
+    let db = DB.get_or_init(storage_conn).await;
+    if !has_manifest(db, hash).await? {
+        return Err(anyhow!("file {} not found", hash));
+    }
+    let manifest = find_manifest(hash).await?;
+    // 已过期但尚未被清理的文件视为不存在
+    if manifest.expired() {
+        return Err(anyhow!("file {} expired", hash));
+    }
+    let transform = ChunkTransform::from_manifest(&manifest)?;
+    // 只有确认分块缺失或损坏时才隔离，读取存储失败可能只是暂时的，直接返回错误
+    let mut content = match read_content(&manifest, transform).await {
+        Err(err) if err.is::<BrokenChunkError>() => {
+            return Err(quarantine(&manifest, err.to_string()).await)
+        }
+        content => content?,
+    };
+
+    if verify {
+        let (data, digest) = task::spawn_blocking(move || {
+            let digest = crypto::sha256_digest(&content);
+            (content, digest)
+        })
+        .await?;
+        if digest != manifest.file_hash {
+            return Err(quarantine(&manifest, format!("digest is {}", digest)).await);
+        }
+        content = data;
+    }
+
+    let mut file_info = FileInfo::new(
+        manifest.file_hash,
+        manifest.file_name,
+        manifest.size,
+        content,
+    );
+    file_info.expires_at = manifest.expires_at;
+    Ok(file_info)
+}
+
+// 记录读取时间，热存储中的文件按一定精度记录，避免每次读取都改写清单
+async fn touch(hash: &str) -> Result<()> {
+    let db = DB.get_or_init(storage_conn).await;
+    let precision = access_precision();
+    let needs_touch = |manifest: &FileManifest| manifest.cold || manifest.idle_secs() >= precision;
+    // 大多数读取无需改写清单，先不加锁检查，需要改写时再加锁重新读取
+    if !needs_touch(&find_manifest(hash).await?) {
+        return Ok(());
+    }
+    let _refs = lock_refs([hash]).await;
+    let mut manifest = find_manifest(hash).await?;
+    if !needs_touch(&manifest) {
+        return Ok(());
+    }
+    manifest.accessed_at = now_secs();
+    db.put(Column::Meta, hash, encode_manifest(&manifest)?)
+        .await
+}
+
+fn access_precision() -> u64 {
+    ACCESS_PRECISION_SECS.min(CONFIG.tier.cold_after_secs / 2)
+}
+
+// 在冷热存储之间移动文件的内容分块：先复制分块，再更新清单，最后删除原分块，返回是否移动
+pub async fn move_tier(hash: &str, cold: bool) -> Result<bool> {
+    let start = Instant::now();
+    let db = DB.get_or_init(storage_conn).await;
+    let cold_db = COLD.get_or_init(cold_conn).await;
+    let (from, to) = if cold { (db, cold_db) } else { (cold_db, db) };
+    let manifest = find_manifest(hash).await?;
+    if manifest.cold == cold {
+        return Ok(false);
+    }
+    for key in manifest.chunks.iter() {
+        let data = from
+            .get(Column::Content, key)
+            .await?
+            .ok_or_else(|| anyhow!("chunk {} not found", key))?;
+        to.put(Column::Content, key, data).await?;
+    }
+
+    {
+        let _refs = lock_refs([hash]).await;
+        // 复制期间文件已被删除，清理复制出的分块
+        if !db.exists(Column::Meta, hash).await? {
+            for key in manifest.chunks.iter() {
+                to.delete(Column::Content, key).await?;
+            }
+            return Ok(false);
+        }
+        let mut manifest = find_manifest(hash).await?;
+        manifest.cold = cold;
+        db.put(Column::Meta, hash, encode_manifest(&manifest)?)
+            .await?;
+    }
+    for key in manifest.chunks.iter() {
+        from.delete(Column::Content, key).await?;
+    }
+    let duration = start.elapsed();
+    info!("move {} to cold: {}, cost {:?}", hash, cold, duration);
+    Ok(true)
+}
+
+// 分块所在的存储层和另一层，未开启分层存储且分块不在冷存储时没有另一层
+async fn tiers(cold: bool) -> (&'static AsyncStorage, Option<&'static AsyncStorage>) {
+    let db = DB.get_or_init(storage_conn).await;
+    if !cold && !CONFIG.tier.enabled {
+        return (db, None);
+    }
+    let cold_db = COLD.get_or_init(cold_conn).await;
+    match cold {
+        true => (cold_db, Some(db)),
+        false => (db, Some(cold_db)),
+    }
+}
+
+pub async fn find_manifest(hash: &str) -> Result<FileManifest> {
+    let db = DB.get_or_init(storage_conn).await;
+    let data = db
+        .get(Column::Meta, hash)
+        .await?
+        .ok_or_else(|| anyhow!("file {} not found", hash))?;
+    decode_manifest(&data)
+}
+
+// 清单不存在时改写旧版本保存的同一文件，启动时的改写完成前也能访问；
+// 改写需要加锁，调用方不能持有该文件的锁
+async fn has_manifest(db: &AsyncStorage, hash: &str) -> Result<bool> {
+    Ok(db.exists(Column::Meta, hash).await? || upgrade_legacy(hash).await?)
+}
+
+// 启动时在后台改写旧版本保存的所有文件，单个文件改写失败时跳过，下次访问时重试
+pub async fn upgrade_legacy_all() {
+    let start = Instant::now();
+    let result: Result<usize> = async {
+        let db = DB.get_or_init(storage_conn).await;
+        let mut upgraded = 0;
+        let mut cursor: Option<String> = None;
+        loop {
+            let items = db.scan(Column::Legacy, "", cursor.as_deref(), 100).await?;
+            if items.is_empty() {
+                break;
+            }
+            cursor = items.last().map(|(key, _)| key.clone());
+            for (hash, _) in items {
+                let result = upgrade_legacy(&hash).await;
+                if let Err(err) = &result {
+                    warn!("upgrade legacy file {} failed: {:?}", hash, err);
+                }
+                if result.unwrap_or(false) {
+                    upgraded += 1;
+                }
+            }
+        }
+        Ok(upgraded)
+    }
+    .await;
+    if let Err(err) = &result {
+        warn!("upgrade legacy files failed: {:?}", err);
+        return;
+    }
+    info!(
+        "upgrade legacy files cost {:?}, upgraded: {}",
+        start.elapsed(),
+        result.unwrap_or_default()
+    );
+}
+
+// 旧版本把整个文件以 FileInfo JSON 保存，改写为清单和分块后删除旧记录，返回是否存在旧记录；
+// 旧版本没有文件名索引，文件名未被其他文件使用时才加入索引
+async fn upgrade_legacy(hash: &str) -> Result<bool> {
+    let db = DB.get_or_init(storage_conn).await;
+    let data = match db.get(Column::Legacy, hash).await? {
+        Some(data) => data,
+        None => return Ok(false),
+    };
+    let mut file_info: FileInfo = serde_json::from_slice(&data)?;
+    file_info.file_hash = hash.to_string();
+    if file_info.file_name.is_empty() {
+        file_info.file_name = hash.to_string();
+    }
+    let name = file_info.file_name.as_str();
+
+    let _refs = lock_names(db, &[hash], &[name]).await?;
+    let mut txn = Txn::new(db);
+    // 加锁前已被并发的请求改写
+    if !txn.exists(Column::Legacy, hash).await? {
+        return Ok(true);
+    }
+    if txn.exists(Column::Meta, hash).await? {
+        let mut manifest = txn.manifest(hash).await?;
+        if !manifest.names.iter().any(|n| n == name) {
+            manifest.names.push(name.to_string());
+            txn.put(Column::Meta, hash, encode_manifest(&manifest)?);
+        }
+    } else {
+        stage_content(&mut txn, &file_info).await?;
+    }
+    if txn.resolve(name).await?.is_none() {
+        txn.put(Column::Names, &name_key(name), hash.as_bytes().to_vec());
+    }
+    txn.delete(Column::Legacy, hash);
+    txn.commit().await?;
+    info!("upgrade legacy file {}", hash);
+    Ok(true)
+}
+
+// 按分块读取的文件内容，超过缓存上限的文件下载时逐块返回，不把整个文件读入内存
+pub enum FileContent {
+    Loaded(FileInfo),
+    Chunked(Box<FileReader>),
+}
+
+pub async fn open(hash: &str) -> Result<FileContent> {
+    // 缓存中只有不超过上限的文件，命中时直接返回
+    if cache_get(hash).is_none() {
+        let db = DB.get_or_init(storage_conn).await;
+        if !has_manifest(db, hash).await? {
+            return Err(anyhow!("file {} not found", hash));
+        }
+        let manifest = find_manifest(hash).await?;
+        if manifest.expired() {
+            return Err(anyhow!("file {} expired", hash));
+        }
+        if manifest.size > CONFIG.cache.max_object_size {
+            if CONFIG.tier.enabled {
+                if let Err(err) = touch(hash).await {
+                    warn!("update access time of {} failed: {:?}", hash, err);
+                }
+            }
+            return Ok(FileContent::Chunked(Box::new(FileReader::new(manifest)?)));
+        }
+    }
+    Ok(FileContent::Loaded(find(hash).await?))
+}
+
+pub async fn open_by_name(name: &str) -> Result<FileContent> {
+    let hash = resolve(name)
+        .await?
+        .ok_or_else(|| anyhow!("file {} not found", name))?;
+    let mut content = open(&hash).await?;
+    match &mut content {
+        FileContent::Loaded(file_info) => file_info.file_name = name.to_string(),
+        FileContent::Chunked(reader) => reader.name = name.to_string(),
+    }
+    Ok(content)
+}
+
+// 从第一块开始按顺序读完时校验 hash，最后一块在校验通过后才返回，
+// 读取部分区间时无法校验，由定期巡检发现损坏
+pub struct FileReader {
+    manifest: FileManifest,
+    name: String,
+    transform: ChunkTransform,
+    hasher: Option<crypto::Sha256>,
+    next: usize,
+}
+
+impl FileReader {
+    fn new(manifest: FileManifest) -> Result<Self> {
+        let transform = ChunkTransform::from_manifest(&manifest)?;
+        let hasher = CONFIG.database.verify_on_read.then(crypto::Sha256::new);
+        Ok(FileReader {
+            name: manifest.file_name.clone(),
+            manifest,
+            transform,
+            hasher,
+            next: 0,
+        })
+    }
+
+    pub fn hash(&self) -> &str {
+        &self.manifest.file_hash
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn size(&self) -> usize {
+        self.manifest.size
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.manifest.chunk_size
+    }
+
+    pub fn expires_at(&self) -> Option<u64> {
+        self.manifest.expires_at
+    }
+
+    pub async fn read_chunk(&mut self, index: usize) -> Result<Vec<u8>> {
+        let key = self
+            .manifest
+            .chunks
+            .get(index)
+            .ok_or_else(|| anyhow!("chunk {} out of range", index))?;
+        let chunk = match load_chunk(key, self.manifest.cold, self.transform).await {
+            Err(err) if err.is::<BrokenChunkError>() => {
+                return Err(quarantine(&self.manifest, err.to_string()).await)
+            }
+            chunk => chunk?,
+        };
+        if index != self.next {
+            self.hasher = None;
+        }
+        self.next = index + 1;
+        if let Some(hasher) = self.hasher.as_mut() {
+            hasher.update(&chunk);
+        }
+        if self.next == self.manifest.chunks.len() {
+            if let Some(hasher) = self.hasher.take() {
+                let digest = hasher.finish();
+                if digest != self.manifest.file_hash {
+                    return Err(quarantine(&self.manifest, format!("digest is {}", digest)).await);
+                }
+            }
+        }
+        Ok(chunk)
+    }
+}
+
+pub async fn read_chunk(manifest: &FileManifest, index: usize) -> Result<Vec<u8>> {
+    let key = manifest
+        .chunks
+        .get(index)
+        .ok_or_else(|| anyhow!("chunk {} out of range", index))?;
+    let transform = ChunkTransform::from_manifest(manifest)?;
+    load_chunk(key, manifest.cold, transform).await
+}
+
+async fn load_chunk(key: &str, cold: bool, transform: ChunkTransform) -> Result<Vec<u8>> {
+    let (db, other) = tiers(cold).await;
+    let mut data = db.get(Column::Content, key).await?;
+    // 迁移中途中断时分块可能仍在另一层
+    if let (None, Some(other)) = (&data, other) {
+        data = other.get(Column::Content, key).await?;
+    }
+    let data = data.ok_or_else(|| BrokenChunkError {
+        key: key.to_string(),
+        reason: "not found".to_string(),
+    })?;
+    transform.decode(key, data).await
+}
+
+async fn read_content(manifest: &FileManifest, transform: ChunkTransform) -> Result<Vec<u8>> {
+    let mut content: Vec<u8> = Vec::with_capacity(manifest.size);
+    for key in manifest.chunks.iter() {
+        content.extend(load_chunk(key, manifest.cold, transform).await?);
+    }
+    Ok(content)
+}
+
+// 将损坏的文件移入隔离区，本节点视为不存在该文件，之后可从其他节点的副本修复
+async fn quarantine(manifest: &FileManifest, reason: String) -> anyhow::Error {
+    let hash = manifest.file_hash.as_str();
+    warn!("file {} is corrupted: {}, move to quarantine", hash, reason);
+    cache_evict(hash);
+    let db = DB.get_or_init(storage_conn).await;
+    let _refs = lock_refs([hash]).await;
+    let (chunks, _) = tiers(manifest.cold).await;
+    let mut records = vec![(db, Column::Meta, hash)];
+    records.extend(
+        manifest
+            .chunks
+            .iter()
+            .map(|key| (chunks, Column::Content, key.as_str())),
+    );
+    for (from, column, key) in records {
+        if let Err(err) = move_to_quarantine(db, from, column, key).await {
+            warn!("quarantine {} failed: {:?}", key, err);
+            // 清单未能移入隔离区时保留分块，下次读取时重新隔离
+            if column == Column::Meta {
+                break;
+            }
+        }
+    }
+    CorruptedError {
+        hash: hash.to_string(),
+        reason,
+    }
+    .into()
+}
+
+// 用其他节点的完好副本修复被隔离的文件，恢复隔离前清单中的所有文件名和过期时间；
+// 隔离时文件名索引未删除，仍指向该内容。修复后删除隔离区中的清单和分块
+pub async fn repair_quarantined(file_info: &FileInfo) -> Result<()> {
+    let start = Instant::now();
+    let db = DB.get_or_init(storage_conn).await;
+    let hash = file_info.file_hash.as_str();
+    let quarantined = match db.get(Column::Quarantine, hash).await? {
+        Some(data) => decode_manifest(&data)?,
+        None => return save(file_info).await,
+    };
+    let names: Vec<&str> = quarantined.names.iter().map(String::as_str).collect();
+    let _refs = lock_names(db, &[hash], &names).await?;
+    let mut txn = Txn::new(db);
+    if !txn.exists(Column::Meta, hash).await? {
+        let mut file_info = file_info.clone();
+        file_info.file_name = quarantined.file_name.clone();
+        file_info.expires_at = quarantined.expires_at;
+        stage_content(&mut txn, &file_info).await?;
+    }
+    // 隔离期间再次上传了相同内容时合并文件名，过期时间与 add_ref 相同取较晚的
+    let mut manifest = txn.manifest(hash).await?;
+    for name in quarantined.names.iter() {
+        if !manifest.names.contains(name) {
+            manifest.names.push(name.clone());
+        }
+    }
+    manifest.expires_at = match (manifest.expires_at, quarantined.expires_at) {
+        (Some(current), Some(expires_at)) => Some(current.max(expires_at)),
+        _ => None,
+    };
+    txn.put(Column::Meta, hash, encode_manifest(&manifest)?);
+
+    txn.delete(Column::Quarantine, hash);
+    for key in quarantined.chunks.iter() {
+        txn.delete(Column::Quarantine, key);
+    }
+    txn.commit().await?;
+    let duration = start.elapsed();
+    info!("repair {} cost {:?}", hash, duration);
+    Ok(())
+}
+
+// 冷存储中的分块同样移入本节点数据库的隔离区
+async fn move_to_quarantine(
+    db: &AsyncStorage,
+    from: &AsyncStorage,
+    column: Column,
+    key: &str,
+) -> Result<()> {
+    // 分块已丢失时只删除原记录，读取或复制失败时不删除
+    if let Some(data) = from.get(column, key).await? {
+        db.put(Column::Quarantine, key, data).await?;
+    }
+    from.delete(column, key).await
+}
+
 pub async fn exists(hash: &str) -> Result<bool> {
     let start = Instant::now();
-    let db = DB.get_or_init(rocksdb_conn).await;
-    let data = db.exists(hash);
+    let db = DB.get_or_init(storage_conn).await;
+    let data = has_manifest(db, hash).await? && !find_manifest(hash).await?.expired();
     let duration = start.elapsed();
     info!("exists cost {:?}", duration);
     Ok(data)
 }
+
+// 删除内容及指向它的所有文件名
+pub async fn delete(hash: &str) -> Result<bool> {
+    let start = Instant::now();
+    let db = DB.get_or_init(storage_conn).await;
+    if !has_manifest(db, hash).await? {
+        return Ok(false);
+    }
+    // 锁住内容及其所有文件名，加锁期间增加了文件名时重新加锁
+    let mut names: Vec<String> = Vec::new();
+    let (_refs, manifest) = loop {
+        let mut keys = vec![hash.to_string()];
+        keys.extend(names.iter().map(|name| name_key(name)));
+        let refs = lock_refs(keys.iter().map(String::as_str)).await;
+        if !db.exists(Column::Meta, hash).await? {
+            return Ok(false);
+        }
+        let manifest = find_manifest(hash).await?;
+        if manifest.names.iter().all(|name| names.contains(name)) {
+            break (refs, manifest);
+        }
+        names = manifest.names;
+    };
+
+    let mut txn = Txn::new(db);
+    for name in manifest.names.iter() {
+        if txn.resolve(name).await?.as_deref() == Some(hash) {
+            txn.delete(Column::Names, &name_key(name));
+        }
+    }
+    delete_content(&mut txn, &manifest);
+    txn.commit().await?;
+    let duration = start.elapsed();
+    info!("delete cost {:?}", duration);
+    Ok(true)
+}
+
+// 只删除文件名，内容在没有其他文件名引用时才删除
+pub async fn delete_name(name: &str) -> Result<bool> {
+    let start = Instant::now();
+    let db = DB.get_or_init(storage_conn).await;
+    let _refs = lock_names(db, &[], &[name]).await?;
+    let mut txn = Txn::new(db);
+    let hash = match txn.resolve(name).await? {
+        Some(hash) => hash,
+        None => return Ok(false),
+    };
+
+    txn.delete(Column::Names, &name_key(name));
+    let released = release_ref(&mut txn, &hash, name).await?;
+    txn.commit().await?;
+    let duration = start.elapsed();
+    info!(
+        "delete name cost {:?}, content deleted: {}",
+        duration, released
+    );
+    Ok(true)
+}
+
+// 先删清单再删分块，删除中途失败也不会读到不完整的文件
+fn delete_content(txn: &mut Txn<'_>, manifest: &FileManifest) {
+    txn.delete(Column::Meta, &manifest.file_hash);
+    for key in manifest.chunks.iter() {
+        match manifest.cold {
+            true => txn.cold_deletes.push(key.clone()),
+            false => txn.delete(Column::Content, key),
+        }
+    }
+}
+
+// cursor 为上一页最后一个文件的 hash，为空时从头开始
+pub async fn list(cursor: Option<&str>, limit: usize) -> Result<FileList> {
+    let start = Instant::now();
+    let db = DB.get_or_init(storage_conn).await;
+    let items = db.scan(Column::Meta, "", cursor, limit).await?;
+
+    let next_cursor = match items.last() {
+        Some((key, _)) if items.len() >= limit => Some(key.clone()),
+        _ => None,
+    };
+    let mut files: Vec<FileHead> = Vec::new();
+    for (_, data) in items {
+        let manifest = decode_manifest(&data)?;
+        if manifest.expired() {
+            continue;
+        }
+        files.push(FileHead::new(
+            true,
+            manifest.file_hash,
+            manifest.file_name,
+            manifest.size,
+        ));
+    }
+    let duration = start.elapsed();
+    info!("list cost {:?}", duration);
+    Ok(FileList::new(files, next_cursor))
+}
+
+pub fn encode_manifest(manifest: &FileManifest) -> Result<Vec<u8>> {
+    encode_record(&records::FileManifest::from(manifest))
+}
+
+pub fn decode_manifest(data: &[u8]) -> Result<FileManifest> {
+    let mut manifest: FileManifest =
+        records::FileManifest::decode(record_body(data)?)?.try_into()?;
+    // 旧版本的清单没有记录文件名引用
+    if manifest.names.is_empty() {
+        manifest.names.push(manifest.file_name.clone());
+    }
+    Ok(manifest)
+}
+
+// 可续传上传的进度中保存的清单
+pub fn decode_upload_manifest(data: &[u8]) -> Result<FileManifest> {
+    records::PartialUpload::decode(record_body(data)?)?
+        .manifest
+        .ok_or_else(|| anyhow!("upload has no manifest"))?
+        .try_into()
+}
+
+// 替换进度中的清单，已接收的字节数和剩余内容不变
+pub fn replace_upload_manifest(data: &[u8], manifest: &FileManifest) -> Result<Vec<u8>> {
+    let mut record = records::PartialUpload::decode(record_body(data)?)?;
+    record.manifest = Some(records::FileManifest::from(manifest));
+    encode_record(&record)
+}
+
+fn encode_record(record: &impl Message) -> Result<Vec<u8>> {
+    let mut data = Vec::with_capacity(2 + record.encoded_len());
+    data.push(RECORD_MAGIC);
+    data.push(RECORD_VERSION);
+    record.encode(&mut data)?;
+    Ok(data)
+}
+
+// 校验记录头，返回其后的 protobuf 内容
+fn record_body(data: &[u8]) -> Result<&[u8]> {
+    match data {
+        [RECORD_MAGIC, RECORD_VERSION, record @ ..] => Ok(record),
+        [RECORD_MAGIC, version, ..] => Err(anyhow!("unsupported record version {}", version)),
+        _ => Err(anyhow!("unknown record format")),
+    }
+}
+
+// 用首个分块试压缩，压缩效果不明显的内容（如已压缩的图片、视频）不再压缩
+async fn choose_codec(sample: &[u8]) -> Result<Codec> {
+    let config = &CONFIG.compression;
+    if config.codec == Codec::None || sample.len() < config.threshold {
+        return Ok(Codec::None);
+    }
+    let size = sample.len();
+    let sample = sample.to_vec();
+    let compressed =
+        task::spawn_blocking(move || compress::compress(config.codec, config.level, &sample))
+            .await??;
+    if compressed.len() as f64 > size as f64 * config.max_ratio {
+        return Ok(Codec::None);
+    }
+    Ok(config.codec)
+}
+
+// 分块写入前先压缩再加密，读取时先解密再解压
+#[derive(Clone, Copy)]
+struct ChunkTransform {
+    codec: Codec,
+    data_key: Option<[u8; KEY_LEN]>,
+}
+
+impl ChunkTransform {
+    fn from_manifest(manifest: &FileManifest) -> Result<Self> {
+        let data_key = match &manifest.wrapped_key {
+            Some(wrapped) => {
+                let master_key = key_service::master_key()?
+                    .ok_or_else(|| anyhow!("{} is encrypted, no master key", manifest.file_hash))?;
+                if manifest.key_id.as_deref() != Some(master_key.id.as_str()) {
+                    return Err(anyhow!(
+                        "{} is wrapped by key {:?}",
+                        manifest.file_hash,
+                        manifest.key_id
+                    ));
+                }
+                Some(master_key.unwrap(wrapped)?)
+            }
+            None => None,
+        };
+        Ok(ChunkTransform {
+            codec: manifest.codec,
+            data_key,
+        })
+    }
+
+    async fn encode(self, key: &str, chunk: Vec<u8>) -> Result<Vec<u8>> {
+        if self.codec == Codec::None && self.data_key.is_none() {
+            return Ok(chunk);
+        }
+        let key = key.to_string();
+        let level = CONFIG.compression.level;
+        task::spawn_blocking(move || {
+            let data = match self.codec {
+                Codec::None => chunk,
+                codec => compress::compress(codec, level, &chunk)?,
+            };
+            match self.data_key {
+                Some(data_key) => crypto::encrypt(&data_key, key.as_bytes(), data),
+                None => Ok(data),
+            }
+        })
+        .await?
+    }
+
+    // 无法解密解压时返回 BrokenChunkError
+    async fn decode(self, key: &str, data: Vec<u8>) -> Result<Vec<u8>> {
+        if self.codec == Codec::None && self.data_key.is_none() {
+            return Ok(data);
+        }
+        let key = key.to_string();
+        let aad = key.clone();
+        let result = task::spawn_blocking(move || {
+            let data = match self.data_key {
+                Some(data_key) => crypto::decrypt(&data_key, aad.as_bytes(), data)?,
+                None => data,
+            };
+            match self.codec {
+                Codec::None => Ok(data),
+                codec => compress::decompress(codec, &data),
+            }
+        })
+        .await?;
+        result.map_err(|err| {
+            BrokenChunkError {
+                key,
+                reason: err.to_string(),
+            }
+            .into()
+        })
+    }
+}
+
+// 暂存一组写操作，commit 时一次写入；读取时优先返回本事务中已暂存的值
+struct Txn<'a> {
+    db: &'a AsyncStorage,
+    ops: Vec<BatchOp>,
+    staged: HashMap<(Column, String), usize>,
+    // 冷存储中待删除的分块，在数据库提交成功后删除
+    cold_deletes: Vec<String>,
+}
+
+impl<'a> Txn<'a> {
+    fn new(db: &'a AsyncStorage) -> Self {
+        Txn {
+            db,
+            ops: Vec::new(),
+            staged: HashMap::new(),
+            cold_deletes: Vec::new(),
+        }
+    }
+
+    async fn exists(&self, column: Column, key: &str) -> Result<bool> {
+        match self.staged.get(&(column, key.to_string())) {
+            Some(&index) => Ok(matches!(self.ops[index], BatchOp::Put(..))),
+            None => self.db.exists(column, key).await,
+        }
+    }
+
+    async fn get(&self, column: Column, key: &str) -> Result<Option<Vec<u8>>> {
+        match self.staged.get(&(column, key.to_string())) {
+            Some(&index) => match &self.ops[index] {
+                BatchOp::Put(_, _, value) => Ok(Some(value.clone())),
+                BatchOp::Delete(..) => Ok(None),
+            },
+            None => self.db.get(column, key).await,
+        }
+    }
+
+    async fn manifest(&self, hash: &str) -> Result<FileManifest> {
+        let data = self
+            .get(Column::Meta, hash)
+            .await?
+            .ok_or_else(|| anyhow!("file {} not found", hash))?;
+        decode_manifest(&data)
+    }
+
+    async fn resolve(&self, name: &str) -> Result<Option<String>> {
+        let key = name_key(name);
+        if !self.exists(Column::Names, &key).await? {
+            return Ok(None);
+        }
+        match self.get(Column::Names, &key).await? {
+            Some(hash) => Ok(Some(String::from_utf8(hash)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn put(&mut self, column: Column, key: &str, value: Vec<u8>) {
+        self.staged
+            .insert((column, key.to_string()), self.ops.len());
+        self.ops.push(BatchOp::Put(column, key.to_string(), value));
+    }
+
+    fn delete(&mut self, column: Column, key: &str) {
+        self.staged
+            .insert((column, key.to_string()), self.ops.len());
+        self.ops.push(BatchOp::Delete(column, key.to_string()));
+    }
+
+    // 提交后淘汰清单有变化的文件的缓存
+    async fn commit(self) -> Result<()> {
+        let changed: Vec<String> = self
+            .ops
+            .iter()
+            .filter_map(|op| match op {
+                BatchOp::Put(Column::Meta, key, _) | BatchOp::Delete(Column::Meta, key) => {
+                    Some(key.clone())
+                }
+                _ => None,
+            })
+            .collect();
+        if !self.ops.is_empty() {
+            self.db.write(self.ops).await?;
+        }
+        for hash in changed.iter() {
+            cache_evict(hash);
+        }
+        if !self.cold_deletes.is_empty() {
+            let cold_db = COLD.get_or_init(cold_conn).await;
+            for key in self.cold_deletes.iter() {
+                cold_db.delete(Column::Content, key).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn chunk_key(hash: &str, index: usize) -> String {
+    format!("{}.{:08}", hash, index)
+}
+
+// 文件名可能包含任意字符，取其 hash 作为索引的 key
+fn name_key(name: &str) -> String {
+    crypto::sha256_digest(name.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{self, memory_storage::MemoryStorage};
+
+    // 所有测试共用进程内的 DB，先于首次访问注入内存存储，各测试使用不同的内容和文件名
+    fn setup() {
+        let _ = storage::init(Box::new(MemoryStorage::default()));
+    }
+
+    fn file(name: &str, content: &[u8]) -> FileInfo {
+        FileInfo::new(
+            crypto::sha256_digest(content),
+            name.to_string(),
+            content.len(),
+            content.to_vec(),
+        )
+    }
+
+    #[tokio::test]
+    async fn save_and_find() {
+        setup();
+        let info = file("save_and_find.txt", b"save and find");
+        save(&info).await.unwrap();
+
+        let found = find(&info.file_hash).await.unwrap();
+        assert_eq!(found.content, info.content);
+        assert_eq!(found.file_name, info.file_name);
+        let found = find_by_name(&info.file_name).await.unwrap();
+        assert_eq!(found.file_hash, info.file_hash);
+        assert!(exists(&info.file_hash).await.unwrap());
+        let stat = stat(&info.file_hash).await.unwrap().unwrap();
+        assert_eq!(stat.size, info.size);
+    }
+
+    #[tokio::test]
+    async fn shared_content_is_released_with_last_name() {
+        setup();
+        let first = file("shared_first.txt", b"shared content");
+        let second = file("shared_second.txt", b"shared content");
+        save(&first).await.unwrap();
+        save(&second).await.unwrap();
+        let manifest = find_manifest(&first.file_hash).await.unwrap();
+        assert_eq!(
+            manifest.names,
+            vec![first.file_name.clone(), second.file_name.clone()]
+        );
+
+        assert!(delete_name(&first.file_name).await.unwrap());
+        assert!(!delete_name(&first.file_name).await.unwrap());
+        assert!(exists(&first.file_hash).await.unwrap());
+        assert_eq!(
+            resolve(&second.file_name).await.unwrap().as_deref(),
+            Some(second.file_hash.as_str())
+        );
+
+        assert!(delete_name(&second.file_name).await.unwrap());
+        assert!(!exists(&first.file_hash).await.unwrap());
+        assert!(stat(&first.file_hash).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn overwrite_name_keeps_old_content() {
+        setup();
+        let old = file("overwrite.txt", b"old content");
+        let new = file("overwrite.txt", b"new content");
+        save(&old).await.unwrap();
+        save(&new).await.unwrap();
+
+        assert_eq!(
+            resolve("overwrite.txt").await.unwrap().as_deref(),
+            Some(new.file_hash.as_str())
+        );
+        let found = find(&old.file_hash).await.unwrap();
+        assert_eq!(found.content, old.content);
+        assert_eq!(found.file_name, "overwrite.txt");
+        assert_eq!(
+            find_by_name("overwrite.txt").await.unwrap().content,
+            new.content
+        );
+
+        // 删除文件名只释放其当前指向的内容
+        assert!(delete_name("overwrite.txt").await.unwrap());
+        assert!(!exists(&new.file_hash).await.unwrap());
+        assert!(exists(&old.file_hash).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn delete_removes_all_names() {
+        setup();
+        let first = file("delete_first.txt", b"delete content");
+        let second = file("delete_second.txt", b"delete content");
+        save(&first).await.unwrap();
+        save(&second).await.unwrap();
+
+        assert!(delete(&first.file_hash).await.unwrap());
+        assert!(!delete(&first.file_hash).await.unwrap());
+        assert!(find(&first.file_hash).await.is_err());
+        assert!(resolve(&first.file_name).await.unwrap().is_none());
+        assert!(resolve(&second.file_name).await.unwrap().is_none());
+    }
+
+    // 基线版本的记录：FileInfo JSON，没有过期时间，保存在默认列族中
+    async fn seed_baseline(name: &str, content: &[u8]) -> String {
+        let hash = crypto::sha256_digest(content);
+        let record = serde_json::json!({
+            "file_hash": hash,
+            "file_name": name,
+            "size": content.len(),
+            "content": content,
+        });
+        let db = DB.get_or_init(storage_conn).await;
+        db.put(Column::Legacy, &hash, serde_json::to_vec(&record).unwrap())
+            .await
+            .unwrap();
+        hash
+    }
+
+    #[tokio::test]
+    async fn baseline_record_is_upgraded() {
+        setup();
+        let db = DB.get_or_init(storage_conn).await;
+        let hash = seed_baseline("baseline_read.txt", b"baseline read").await;
+        let found = find(&hash).await.unwrap();
+        assert_eq!(found.content, b"baseline read");
+        assert_eq!(found.file_name, "baseline_read.txt");
+        assert!(db.exists(Column::Meta, &hash).await.unwrap());
+        assert!(!db.exists(Column::Legacy, &hash).await.unwrap());
+        assert_eq!(
+            resolve("baseline_read.txt").await.unwrap().as_deref(),
+            Some(hash.as_str())
+        );
+
+        let hash = seed_baseline("baseline_scan.txt", b"baseline scan").await;
+        upgrade_legacy_all().await;
+        assert!(!db.exists(Column::Legacy, &hash).await.unwrap());
+        let stat = stat(&hash).await.unwrap().unwrap();
+        assert_eq!(stat.size, b"baseline scan".len());
+    }
+
+    #[tokio::test]
+    async fn repair_restores_all_names() {
+        setup();
+        let db = DB.get_or_init(storage_conn).await;
+        let expires_at = Some(now_secs() + 3600);
+        let mut first = file("repair_first.txt", b"repair content");
+        first.expires_at = expires_at;
+        let mut second = file("repair_second.txt", b"repair content");
+        second.expires_at = expires_at;
+        save(&first).await.unwrap();
+        save(&second).await.unwrap();
+
+        let hash = first.file_hash.as_str();
+        db.put(Column::Content, &chunk_key(hash, 0), b"broken".to_vec())
+            .await
+            .unwrap();
+        let err = verify(hash).await.unwrap_err();
+        assert!(err.is::<CorruptedError>());
+        assert!(!exists(hash).await.unwrap());
+
+        // 其他节点返回的副本只带一个文件名
+        repair_quarantined(&second).await.unwrap();
+        let manifest = find_manifest(hash).await.unwrap();
+        assert_eq!(
+            manifest.names,
+            vec![first.file_name.clone(), second.file_name.clone()]
+        );
+        assert_eq!(manifest.expires_at, expires_at);
+        assert!(!db.exists(Column::Quarantine, hash).await.unwrap());
+        assert_eq!(find(hash).await.unwrap().content, first.content);
+
+        assert!(delete_name(&second.file_name).await.unwrap());
+        assert_eq!(
+            find_by_name(&first.file_name).await.unwrap().content,
+            first.content
+        );
+    }
+
+    #[tokio::test]
+    async fn expired_file_is_not_found() {
+        setup();
+        let mut info = file("expired.txt", b"expired content");
+        info.expires_at = Some(1);
+        save(&info).await.unwrap();
+
+        assert!(stat(&info.file_hash).await.unwrap().is_none());
+        assert!(!exists(&info.file_hash).await.unwrap());
+        assert!(find(&info.file_hash).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn large_file_is_read_by_chunk() {
+        setup();
+        let content: Vec<u8> = (0..CONFIG.cache.max_object_size + 1000)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let info = file("large.bin", &content);
+        save(&info).await.unwrap();
+
+        let mut reader = match open(&info.file_hash).await.unwrap() {
+            FileContent::Chunked(reader) => reader,
+            FileContent::Loaded(_) => panic!("large file is loaded"),
+        };
+        let chunks = info.size.div_ceil(reader.chunk_size());
+        let last = reader.read_chunk(chunks - 1).await.unwrap();
+        assert_eq!(last, content[(chunks - 1) * reader.chunk_size()..]);
+        let mut read = Vec::new();
+        for index in 0..chunks {
+            read.extend(reader.read_chunk(index).await.unwrap());
+        }
+        assert_eq!(read, content);
+        assert!(reader.read_chunk(chunks).await.is_err());
+    }
+}
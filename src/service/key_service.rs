@@ -0,0 +1,119 @@
+use crate::config::CONFIG;
+use crate::service::file_service;
+use crate::storage::{storage_conn, Column, DB};
+use crate::util::crypto::{self, KEY_LEN};
+use anyhow::{anyhow, Result};
+use data_encoding::{HEXLOWER_PERMISSIVE, HEXUPPER};
+use once_cell::sync::OnceCell;
+use std::{env, fs};
+use tracing::info;
+
+static MASTER_KEY: OnceCell<Option<MasterKey>> = OnceCell::new();
+
+// 主密钥只用于加密每个文件的数据密钥，轮换主密钥时无需重写文件内容
+pub struct MasterKey {
+    pub id: String,
+    key: [u8; KEY_LEN],
+}
+
+impl MasterKey {
+    pub fn parse(text: &str) -> Result<Self> {
+        let bytes = HEXLOWER_PERMISSIVE.decode(text.trim().as_bytes())?;
+        if bytes.len() != KEY_LEN {
+            return Err(anyhow!("master key must be {} bytes", KEY_LEN));
+        }
+        let mut key = [0u8; KEY_LEN];
+        key.copy_from_slice(&bytes);
+        let id = crypto::sha256_digest(&key)[..16].to_string();
+        Ok(MasterKey { id, key })
+    }
+
+    pub fn load(path: &str) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    pub fn wrap(&self, data_key: &[u8; KEY_LEN]) -> Result<String> {
+        let wrapped = crypto::encrypt(&self.key, self.id.as_bytes(), data_key.to_vec())?;
+        Ok(HEXUPPER.encode(&wrapped))
+    }
+
+    pub fn unwrap(&self, wrapped: &str) -> Result<[u8; KEY_LEN]> {
+        let wrapped = HEXUPPER.decode(wrapped.as_bytes())?;
+        let bytes = crypto::decrypt(&self.key, self.id.as_bytes(), wrapped)?;
+        let mut data_key = [0u8; KEY_LEN];
+        data_key.copy_from_slice(&bytes);
+        Ok(data_key)
+    }
+}
+
+pub fn master_key() -> Result<Option<&'static MasterKey>> {
+    let key = MASTER_KEY.get_or_try_init(|| {
+        let config = &CONFIG.encryption;
+        if !config.enabled {
+            return Ok(None);
+        }
+        if let Some(text) = config.key_env.as_ref().and_then(|name| env::var(name).ok()) {
+            return MasterKey::parse(&text).map(Some);
+        }
+        match &config.key_file {
+            Some(path) => MasterKey::load(path).map(Some),
+            None => Err(anyhow!("encryption enabled but no master key configured")),
+        }
+    })?;
+    Ok(key.as_ref())
+}
+
+// 用新主密钥重新加密所有文件和未完成上传的数据密钥，返回处理的文件数
+pub async fn rotate(new_key: &MasterKey) -> Result<usize> {
+    let old_key = master_key()?.ok_or_else(|| anyhow!("encryption not enabled"))?;
+    let count = rotate_column(Column::Meta, old_key, new_key).await?;
+    // 未完成上传的清单保存在进度中，完成时使用其中的数据密钥
+    let uploads = rotate_column(Column::Uploads, old_key, new_key).await?;
+    info!(
+        "rotate master key {} -> {}, {} files, {} uploads",
+        old_key.id, new_key.id, count, uploads
+    );
+    Ok(count + uploads)
+}
+
+async fn rotate_column(column: Column, old_key: &MasterKey, new_key: &MasterKey) -> Result<usize> {
+    let db = DB.get_or_init(storage_conn).await;
+
+    let mut count = 0;
+    let mut cursor: Option<String> = None;
+    loop {
+        let items = db.scan(column, "", cursor.as_deref(), 100).await?;
+        if items.is_empty() {
+            break;
+        }
+        cursor = items.last().map(|(key, _)| key.clone());
+
+        for (key, data) in items {
+            let mut manifest = match column {
+                Column::Uploads => file_service::decode_upload_manifest(&data)?,
+                _ => file_service::decode_manifest(&data)?,
+            };
+            let wrapped = match &manifest.wrapped_key {
+                Some(wrapped) => wrapped,
+                None => continue,
+            };
+            // 中断后重新执行时跳过已轮换的文件
+            if manifest.key_id.as_deref() == Some(new_key.id.as_str()) {
+                continue;
+            }
+            if manifest.key_id.as_deref() != Some(old_key.id.as_str()) {
+                return Err(anyhow!("{} is wrapped by unknown key", key));
+            }
+            let data_key = old_key.unwrap(wrapped)?;
+            manifest.wrapped_key = Some(new_key.wrap(&data_key)?);
+            manifest.key_id = Some(new_key.id.clone());
+            let data = match column {
+                Column::Uploads => file_service::replace_upload_manifest(&data, &manifest)?,
+                _ => file_service::encode_manifest(&manifest)?,
+            };
+            db.put(column, &key, data).await?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
@@ -1 +1,7 @@
+pub mod backup_service;
+pub mod capacity_service;
+pub mod expire_service;
 pub mod file_service;
+pub mod key_service;
+pub mod scrub_service;
+pub mod tier_service;
@@ -0,0 +1,106 @@
+use crate::config::CONFIG;
+use crate::handler::file_handler;
+use crate::model::file_model::FileInfo;
+use crate::service::file_service::{self, CorruptedError};
+use crate::storage::{storage_conn, Column, DB};
+use crate::util::crypto;
+use anyhow::{anyhow, Result};
+use std::time::Duration;
+use tokio::task;
+use tokio::time::{self, Instant};
+use tracing::{info, warn};
+
+// 后台巡检：按配置的速率逐个重新校验本节点的文件，发现损坏后从其他节点下载完好的副本修复
+pub async fn run() {
+    let config = &CONFIG.scrub;
+    let period = Duration::from_secs_f64(1.0 / config.rate.max(0.001));
+    loop {
+        match scrub(period).await {
+            Ok((checked, repaired, failed)) => info!(
+                "scrub finished, checked: {}, repaired: {}, failed: {}",
+                checked, repaired, failed
+            ),
+            Err(err) => warn!("scrub failed: {:?}", err),
+        }
+        time::sleep(Duration::from_secs(config.interval_secs)).await;
+    }
+}
+
+async fn scrub(period: Duration) -> Result<(usize, usize, usize)> {
+    let start = Instant::now();
+    let db = DB.get_or_init(storage_conn).await;
+    let mut ticker = time::interval(period);
+    let (mut checked, mut repaired, mut failed) = (0, 0, 0);
+
+    let mut cursor: Option<String> = None;
+    loop {
+        let items = db.scan(Column::Meta, "", cursor.as_deref(), 100).await?;
+        if items.is_empty() {
+            break;
+        }
+        cursor = items.last().map(|(key, _)| key.clone());
+
+        for (hash, _) in items {
+            ticker.tick().await;
+            checked += 1;
+            let err = match file_service::verify(&hash).await {
+                Ok(_) => continue,
+                Err(err) => err,
+            };
+            if !err.is::<CorruptedError>() {
+                warn!("scrub {} failed: {:?}", hash, err);
+                continue;
+            }
+            match repair(&hash).await {
+                Ok(server) => {
+                    info!("file {} repaired from {}", hash, server);
+                    repaired += 1;
+                }
+                Err(err) => {
+                    warn!("file {} repair failed: {:?}", hash, err);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    info!("scrub cost {:?}", start.elapsed());
+    Ok((checked, repaired, failed))
+}
+
+async fn repair(hash: &str) -> Result<String> {
+    let local = String::from("http://") + &CONFIG.server.grpc_address;
+    for server in CONFIG.cluster.servers.iter() {
+        if *server == local {
+            continue;
+        }
+        match download_other(hash, server).await {
+            Ok(file_info) => {
+                file_service::repair_quarantined(&file_info).await?;
+                return Ok(server.clone());
+            }
+            Err(err) => info!("download {} from {} failed: {:?}", hash, server, err),
+        }
+    }
+    Err(anyhow!("no valid replica found"))
+}
+
+async fn download_other(hash: &str, server: &str) -> Result<FileInfo> {
+    let response = file_handler::fetch_other(hash, "", server).await?;
+    // 其他节点的副本同样可能损坏，保存前先校验
+    let (response, digest) = task::spawn_blocking(move || {
+        let digest = crypto::sha256_digest(&response.content);
+        (response, digest)
+    })
+    .await?;
+    if digest != hash {
+        return Err(anyhow!("replica digest is {}", digest));
+    }
+    let mut file_info = FileInfo::new(
+        hash.to_string(),
+        response.name,
+        response.content.len(),
+        response.content,
+    );
+    file_info.expires_at = (response.expires_at > 0).then_some(response.expires_at);
+    Ok(file_info)
+}
@@ -0,0 +1,59 @@
+use crate::config::CONFIG;
+use crate::service::file_service;
+use crate::storage::{storage_conn, Column, DB};
+use anyhow::Result;
+use std::time::Duration;
+use tokio::time::{self, Instant};
+use tracing::{info, warn};
+
+// 定期将长时间未读取的文件移入冷存储，冷存储中近期被读取过的文件移回热存储
+pub async fn run() {
+    let interval = Duration::from_secs(CONFIG.tier.interval_secs);
+    loop {
+        match migrate().await {
+            Ok((cooled, warmed)) => info!(
+                "tier migration finished, to cold: {}, to hot: {}",
+                cooled, warmed
+            ),
+            Err(err) => warn!("tier migration failed: {:?}", err),
+        }
+        time::sleep(interval).await;
+    }
+}
+
+async fn migrate() -> Result<(usize, usize)> {
+    let start = Instant::now();
+    let db = DB.get_or_init(storage_conn).await;
+    let (mut cooled, mut warmed) = (0, 0);
+
+    let mut cursor: Option<String> = None;
+    loop {
+        let items = db.scan(Column::Meta, "", cursor.as_deref(), 100).await?;
+        if items.is_empty() {
+            break;
+        }
+        cursor = items.last().map(|(key, _)| key.clone());
+
+        for (hash, data) in items {
+            let manifest = match file_service::decode_manifest(&data) {
+                Ok(manifest) => manifest,
+                Err(err) => {
+                    warn!("decode {} failed: {:?}", hash, err);
+                    continue;
+                }
+            };
+            let cold = manifest.idle_secs() >= CONFIG.tier.cold_after_secs;
+            if manifest.expired() || manifest.cold == cold {
+                continue;
+            }
+            match file_service::move_tier(&hash, cold).await {
+                Ok(true) if cold => cooled += 1,
+                Ok(true) => warmed += 1,
+                Ok(false) => {}
+                Err(err) => warn!("move {} failed: {:?}", hash, err),
+            }
+        }
+    }
+    info!("tier migration cost {:?}", start.elapsed());
+    Ok((cooled, warmed))
+}
@@ -0,0 +1,71 @@
+use crate::storage::{BatchOp, Capacity, Column, Storage};
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::task;
+
+// 存储实现都是同步阻塞的，统一放到 blocking 线程池中执行，避免阻塞 tokio 工作线程
+#[derive(Clone)]
+pub struct AsyncStorage(Arc<dyn Storage>);
+
+impl AsyncStorage {
+    pub fn new(storage: Box<dyn Storage>) -> Self {
+        Self(Arc::from(storage))
+    }
+
+    pub async fn get(&self, column: Column, key: &str) -> Result<Option<Vec<u8>>> {
+        let storage = self.0.clone();
+        let key = key.to_string();
+        task::spawn_blocking(move || storage.get(column, &key)).await?
+    }
+
+    pub async fn exists(&self, column: Column, key: &str) -> Result<bool> {
+        let storage = self.0.clone();
+        let key = key.to_string();
+        Ok(task::spawn_blocking(move || storage.exists(column, &key)).await?)
+    }
+
+    pub async fn put(&self, column: Column, key: &str, value: Vec<u8>) -> Result<()> {
+        let storage = self.0.clone();
+        let key = key.to_string();
+        task::spawn_blocking(move || storage.put(column, &key, value)).await?
+    }
+
+    pub async fn delete(&self, column: Column, key: &str) -> Result<()> {
+        let storage = self.0.clone();
+        let key = key.to_string();
+        task::spawn_blocking(move || storage.delete(column, &key)).await?
+    }
+
+    pub async fn scan(
+        &self,
+        column: Column,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        let storage = self.0.clone();
+        let prefix = prefix.to_string();
+        let after = after.map(ToString::to_string);
+        task::spawn_blocking(move || storage.scan(column, &prefix, after.as_deref(), limit)).await?
+    }
+
+    pub async fn write(&self, batch: Vec<BatchOp>) -> Result<()> {
+        let storage = self.0.clone();
+        task::spawn_blocking(move || storage.write(batch)).await?
+    }
+
+    pub fn atomic_write(&self) -> bool {
+        self.0.atomic_write()
+    }
+
+    pub async fn capacity(&self) -> Result<Option<Capacity>> {
+        let storage = self.0.clone();
+        task::spawn_blocking(move || storage.capacity()).await?
+    }
+
+    pub async fn checkpoint(&self, path: PathBuf) -> Result<()> {
+        let storage = self.0.clone();
+        task::spawn_blocking(move || storage.checkpoint(&path)).await?
+    }
+}
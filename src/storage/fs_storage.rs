@@ -0,0 +1,181 @@
+use crate::storage::{volume_capacity, Capacity, Column, Storage};
+use anyhow::{anyhow, Result};
+use std::{
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+// 以普通文件保存数据，按 key 前缀分两级目录，如 meta/AB/CD/ABCD...
+#[derive(Debug)]
+pub struct FsStorage(PathBuf);
+
+impl FsStorage {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        let root = path.as_ref().to_path_buf();
+        for column in Column::ALL {
+            fs::create_dir_all(root.join(column.name())).unwrap();
+        }
+        Self(root)
+    }
+
+    fn path(&self, column: Column, key: &str) -> Result<PathBuf> {
+        // 前 4 个字符用作目录名，只允许十六进制字符，避免出现 . 或 .. 这样的目录
+        if key.len() < 4
+            || !key[..4].chars().all(|c| c.is_ascii_hexdigit())
+            || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '.')
+        {
+            return Err(anyhow!("invalid key {}", key));
+        }
+        Ok(self
+            .0
+            .join(column.name())
+            .join(&key[0..2])
+            .join(&key[2..4])
+            .join(key))
+    }
+}
+
+impl Storage for FsStorage {
+    fn get(&self, column: Column, key: &str) -> Result<Option<Vec<u8>>> {
+        match fs::read(self.path(column, key)?) {
+            Ok(val) => Ok(Some(val)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn exists(&self, column: Column, key: &str) -> bool {
+        match self.path(column, key) {
+            Ok(path) => path.is_file(),
+            Err(_) => false,
+        }
+    }
+
+    fn put(&self, column: Column, key: &str, value: Vec<u8>) -> Result<()> {
+        let path = self.path(column, key)?;
+        let dir = path.parent().ok_or_else(|| anyhow!("invalid path"))?;
+        fs::create_dir_all(dir)?;
+        // 先写临时文件再改名，避免读到写了一半的文件
+        let tmp = dir.join(format!(".{}.tmp", key));
+        fs::write(&tmp, value)?;
+        fs::rename(&tmp, &path)?;
+        Ok(())
+    }
+
+    fn delete(&self, column: Column, key: &str) -> Result<()> {
+        match fs::remove_file(self.path(column, key)?) {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+
+    fn scan(
+        &self,
+        column: Column,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        // 目录名就是 key 的前缀，按顺序遍历目录即按 key 的顺序遍历，取够 limit 个即可停止
+        let mut items = Vec::new();
+        if limit == 0 {
+            return Ok(items);
+        }
+        let root = self.0.join(column.name());
+        for first in sorted_names(&root)? {
+            if !shard_matches(&first, prefix, after) {
+                continue;
+            }
+            for second in sorted_names(&root.join(&first))? {
+                let shard = format!("{}{}", first, second);
+                if !shard_matches(&shard, prefix, after) {
+                    continue;
+                }
+                for key in sorted_names(&root.join(&first).join(&second))? {
+                    // 跳过写入中的临时文件
+                    if key.starts_with('.') || !key.starts_with(prefix) {
+                        continue;
+                    }
+                    if matches!(after, Some(after) if key.as_str() <= after) {
+                        continue;
+                    }
+                    if let Ok(Some(value)) = self.get(column, &key) {
+                        items.push((key, value));
+                        if items.len() >= limit {
+                            return Ok(items);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(items)
+    }
+
+    fn capacity(&self) -> Result<Option<Capacity>> {
+        Ok(Some(volume_capacity(&self.0)?))
+    }
+}
+
+fn sorted_names(dir: &Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        names.push(entry?.file_name().to_string_lossy().to_string());
+    }
+    names.sort();
+    Ok(names)
+}
+
+// 判断以 shard 开头的 key 中是否可能有以 prefix 开头且大于 after 的
+fn shard_matches(shard: &str, prefix: &str, after: Option<&str>) -> bool {
+    let len = shard.len().min(prefix.len());
+    if shard.get(..len) != prefix.get(..len) {
+        return false;
+    }
+    match after {
+        Some(after) => {
+            let len = shard.len().min(after.len());
+            shard.get(..len) >= after.get(..len)
+        }
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_encoding::HEXUPPER;
+
+    fn storage() -> FsStorage {
+        let name = HEXUPPER.encode(&rand::random::<[u8; 8]>());
+        FsStorage::new(std::env::temp_dir().join(name))
+    }
+
+    #[test]
+    fn rejects_non_hex_shard() {
+        let storage = storage();
+        assert!(storage.put(Column::Meta, "....x", vec![1]).is_err());
+        assert!(storage.put(Column::Meta, "AB..CD", vec![1]).is_err());
+        assert!(storage.put(Column::Meta, "ABCD.00000001", vec![1]).is_ok());
+        fs::remove_dir_all(&storage.0).unwrap();
+    }
+
+    #[test]
+    fn scan_pages_in_key_order() {
+        let storage = storage();
+        let keys = ["ABCD01", "ABCD02", "AC0000", "FF0000", "0000AB"];
+        for key in keys {
+            storage.put(Column::Meta, key, key.into()).unwrap();
+        }
+        let page = |after, limit| -> Vec<String> {
+            let items = storage.scan(Column::Meta, "", after, limit).unwrap();
+            items.into_iter().map(|(key, _)| key).collect()
+        };
+        assert_eq!(page(None, 2), ["0000AB", "ABCD01"]);
+        assert_eq!(page(Some("ABCD01"), 2), ["ABCD02", "AC0000"]);
+        assert_eq!(page(Some("AC0000"), 10), ["FF0000"]);
+        let items = storage.scan(Column::Meta, "AB", None, 10).unwrap();
+        assert_eq!(items.len(), 2);
+        fs::remove_dir_all(&storage.0).unwrap();
+    }
+}
@@ -0,0 +1,173 @@
+use crate::storage::{BatchOp, Capacity, Column, Storage};
+use anyhow::{anyhow, Result};
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Bound,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock,
+    },
+};
+
+// 数据只保存在内存中，进程退出即丢失，用于测试和临时缓存节点
+#[derive(Debug)]
+pub struct MemoryStorage {
+    columns: HashMap<Column, RwLock<BTreeMap<String, Vec<u8>>>>,
+    used: AtomicUsize,
+    limit: Option<usize>,
+}
+
+impl MemoryStorage {
+    pub fn new(limit: Option<usize>) -> Self {
+        let columns = Column::ALL
+            .iter()
+            .map(|column| (*column, RwLock::new(BTreeMap::new())))
+            .collect();
+        Self {
+            columns,
+            used: AtomicUsize::new(0),
+            limit,
+        }
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::Relaxed)
+    }
+
+    fn column(&self, column: Column) -> &RwLock<BTreeMap<String, Vec<u8>>> {
+        &self.columns[&column]
+    }
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn get(&self, column: Column, key: &str) -> Result<Option<Vec<u8>>> {
+        let map = self.column(column).read().map_err(|e| anyhow!("{}", e))?;
+        Ok(map.get(key).cloned())
+    }
+
+    fn exists(&self, column: Column, key: &str) -> bool {
+        match self.column(column).read() {
+            Ok(map) => map.contains_key(key),
+            Err(_) => false,
+        }
+    }
+
+    fn put(&self, column: Column, key: &str, value: Vec<u8>) -> Result<()> {
+        let mut map = self.column(column).write().map_err(|e| anyhow!("{}", e))?;
+        let old = map.get(key).map_or(0, |old| key.len() + old.len());
+        let size = key.len() + value.len();
+        if let Some(limit) = self.limit {
+            if self.used() + size > limit + old {
+                return Err(anyhow!(
+                    "memory storage full, used {} of {} bytes",
+                    self.used(),
+                    limit
+                ));
+            }
+        }
+        map.insert(key.to_string(), value);
+        self.used.fetch_add(size, Ordering::Relaxed);
+        self.used.fetch_sub(old, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn delete(&self, column: Column, key: &str) -> Result<()> {
+        let mut map = self.column(column).write().map_err(|e| anyhow!("{}", e))?;
+        if let Some(value) = map.remove(key) {
+            self.used
+                .fetch_sub(key.len() + value.len(), Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    fn scan(
+        &self,
+        column: Column,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        let map = self.column(column).read().map_err(|e| anyhow!("{}", e))?;
+        let start = match after {
+            Some(after) if after >= prefix => Bound::Excluded(after),
+            _ => Bound::Included(prefix),
+        };
+        let items = map
+            .range::<str, _>((start, Bound::Unbounded))
+            .take_while(|(key, _)| key.starts_with(prefix))
+            .take(limit)
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        Ok(items)
+    }
+
+    // 按固定顺序锁住所有列后整批写入，超出容量时撤销已写入的操作
+    fn write(&self, batch: Vec<BatchOp>) -> Result<()> {
+        let mut maps = HashMap::new();
+        for column in Column::ALL {
+            let map = self.column(column).write().map_err(|e| anyhow!("{}", e))?;
+            maps.insert(column, map);
+        }
+
+        let mut undo = Vec::new();
+        let (mut added, mut removed) = (0, 0);
+        for op in batch {
+            let (column, key, value) = match op {
+                BatchOp::Put(column, key, value) => (column, key, Some(value)),
+                BatchOp::Delete(column, key) => (column, key, None),
+            };
+            let map = maps.get_mut(&column).unwrap();
+            let old = match value {
+                Some(value) => {
+                    added += key.len() + value.len();
+                    map.insert(key.clone(), value)
+                }
+                None => map.remove(&key),
+            };
+            if let Some(old) = &old {
+                removed += key.len() + old.len();
+            }
+            undo.push((column, key, old));
+        }
+
+        if let Some(limit) = self.limit {
+            if self.used() + added > limit + removed {
+                for (column, key, old) in undo.into_iter().rev() {
+                    let map = maps.get_mut(&column).unwrap();
+                    match old {
+                        Some(old) => map.insert(key, old),
+                        None => map.remove(&key),
+                    };
+                }
+                return Err(anyhow!(
+                    "memory storage full, used {} of {} bytes",
+                    self.used(),
+                    limit
+                ));
+            }
+        }
+        self.used.fetch_add(added, Ordering::Relaxed);
+        self.used.fetch_sub(removed, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn atomic_write(&self) -> bool {
+        true
+    }
+
+    // 未设置容量上限时不限制写入
+    fn capacity(&self) -> Result<Option<Capacity>> {
+        let used = self.used() as u64;
+        Ok(self.limit.map(|limit| Capacity {
+            total: limit as u64,
+            used,
+            available: (limit as u64).saturating_sub(used),
+        }))
+    }
+}
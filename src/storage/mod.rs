@@ -1,9 +1,154 @@
+pub mod async_storage;
+pub mod fs_storage;
+pub mod memory_storage;
 pub mod rocksdb_storage;
-use anyhow::Result;
-pub use rocksdb_storage::{rocksdb_conn, DB};
+use crate::config::{configs::Engine, CONFIG};
+use anyhow::{anyhow, Result};
+pub use async_storage::AsyncStorage;
+pub use fs_storage::FsStorage;
+pub use memory_storage::MemoryStorage;
+pub use rocksdb_storage::RocksDbStorage;
+use std::ffi::CString;
+use std::io;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use tokio::sync::OnceCell;
 
-pub trait Storage {
-    fn get(&self, key: &str) -> Result<Option<Vec<u8>>>;
-    fn exists(&self, key: &str) -> bool;
-    fn put(&self, key: &str, value: Vec<u8>) -> Result<()>;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Column {
+    // 文件元数据：文件名、大小、时间戳、分块列表
+    Meta,
+    // 文件内容分块
+    Content,
+    // 校验失败的文件元数据和分块
+    Quarantine,
+    // 文件名索引：文件名的 hash -> 文件内容的 hash
+    Names,
+    // 未完成的可续传上传：上传 id -> 上传进度
+    Uploads,
+    // 旧版本保存的文件：文件 hash -> 包含完整内容的 FileInfo JSON，启动后逐个改写为清单和分块
+    Legacy,
+}
+
+impl Column {
+    pub const ALL: [Column; 6] = [
+        Column::Meta,
+        Column::Content,
+        Column::Quarantine,
+        Column::Names,
+        Column::Uploads,
+        Column::Legacy,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Column::Meta => "meta",
+            Column::Content => "content",
+            Column::Quarantine => "quarantine",
+            Column::Names => "names",
+            Column::Uploads => "uploads",
+            // 旧版本直接写入 RocksDB 的默认列族
+            Column::Legacy => "default",
+        }
+    }
+}
+
+// 批量写入中的一个操作
+#[derive(Debug)]
+pub enum BatchOp {
+    Put(Column, String, Vec<u8>),
+    Delete(Column, String),
+}
+
+// 存储的总容量和已用字节数
+#[derive(Debug, Clone, Copy)]
+pub struct Capacity {
+    pub total: u64,
+    pub used: u64,
+    pub available: u64,
+}
+
+pub trait Storage: Send + Sync {
+    // key 不存在时返回 None，读取失败时返回错误
+    fn get(&self, column: Column, key: &str) -> Result<Option<Vec<u8>>>;
+    fn exists(&self, column: Column, key: &str) -> bool;
+    // 覆盖已有的值：清单在增减文件名引用、轮换密钥、冷热迁移时原地改写。
+    // 依赖“已存在则不写入”的调用方需在同一批次中自行检查
+    fn put(&self, column: Column, key: &str, value: Vec<u8>) -> Result<()>;
+    fn delete(&self, column: Column, key: &str) -> Result<()>;
+    // 按 key 升序返回以 prefix 开头、且大于 after 的最多 limit 条记录
+    fn scan(
+        &self,
+        column: Column,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, Vec<u8>)>>;
+    // 按顺序执行一批写操作，默认实现逐个执行，中途失败时已执行的操作不会撤销
+    fn write(&self, batch: Vec<BatchOp>) -> Result<()> {
+        for op in batch {
+            match op {
+                BatchOp::Put(column, key, value) => self.put(column, &key, value)?,
+                BatchOp::Delete(column, key) => self.delete(column, &key)?,
+            }
+        }
+        Ok(())
+    }
+    // write 是否整批原子提交，全部成功或全部失败
+    fn atomic_write(&self) -> bool {
+        false
+    }
+    // 无法统计容量时返回 None
+    fn capacity(&self) -> Result<Option<Capacity>> {
+        Ok(None)
+    }
+    // 在 path 创建一致性快照，创建过程中不影响读写，path 必须不存在
+    fn checkpoint(&self, _path: &Path) -> Result<()> {
+        Err(anyhow!("checkpoint is not supported by this engine"))
+    }
+}
+
+// path 所在文件系统的容量，已用字节数包含其他程序占用的空间
+pub fn volume_capacity(path: &Path) -> Result<Capacity> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let block_size = stat.f_frsize as u64;
+    let total = stat.f_blocks as u64 * block_size;
+    let free = stat.f_bfree as u64 * block_size;
+    Ok(Capacity {
+        total,
+        used: total - free,
+        available: stat.f_bavail as u64 * block_size,
+    })
+}
+
+pub static DB: OnceCell<AsyncStorage> = OnceCell::const_new();
+
+// 在首次访问 DB 之前注入自定义的存储实现，如测试中使用 MemoryStorage
+pub fn init(storage: Box<dyn Storage>) -> Result<()> {
+    DB.set(AsyncStorage::new(storage))
+        .map_err(|_| anyhow!("storage already initialized"))
+}
+
+// 冷存储，保存长时间未读取的文件的内容分块
+pub static COLD: OnceCell<AsyncStorage> = OnceCell::const_new();
+
+pub async fn cold_conn() -> AsyncStorage {
+    let url = &CONFIG.tier.cold_url;
+    tracing::info!("cold storage connected, url: {}", url);
+    AsyncStorage::new(Box::new(FsStorage::new(url)))
+}
+
+pub async fn storage_conn() -> AsyncStorage {
+    let url = &CONFIG.database.url;
+    let db: Box<dyn Storage> = match CONFIG.database.engine {
+        Engine::Rocksdb => Box::new(RocksDbStorage::new(url, &CONFIG.database.rocksdb)),
+        Engine::Fs => Box::new(FsStorage::new(url)),
+        Engine::Memory => Box::new(MemoryStorage::new(CONFIG.database.memory_limit)),
+    };
+    tracing::info!("database connected, engine: {:?}", CONFIG.database.engine);
+    AsyncStorage::new(db)
 }
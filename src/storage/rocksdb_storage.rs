@@ -1,47 +1,180 @@
-use crate::{config::CONFIG, storage::Storage};
+use crate::config::configs::{CompactionStyle, RocksDb, RocksDbCompression};
+use crate::storage::{volume_capacity, BatchOp, Capacity, Column, Storage};
 use anyhow::{anyhow, Ok, Result};
-use rocksdb::DB;
+use rocksdb::{
+    checkpoint::Checkpoint, BlockBasedOptions, Cache, ColumnFamily, ColumnFamilyDescriptor,
+    DBCompactionStyle, DBCompressionType, Direction, IteratorMode, Options, WriteBatch, DB,
+};
 use std::path::Path;
-use tokio::sync::OnceCell;
 
 #[derive(Debug)]
 pub struct RocksDbStorage(DB);
 
 impl RocksDbStorage {
-    pub fn new(path: impl AsRef<Path>) -> Self {
-        Self(DB::open_default(path).unwrap())
+    pub fn new(path: impl AsRef<Path>, config: &RocksDb) -> Self {
+        let mut options = Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        if let Some(max_open_files) = config.max_open_files {
+            options.set_max_open_files(max_open_files);
+        }
+        // 所有列族共用一个 block cache
+        let cache = config
+            .block_cache_size
+            .map(|size| Cache::new_lru_cache(size).unwrap());
+        let families = Column::ALL.iter().map(|column| {
+            ColumnFamilyDescriptor::new(
+                column.name(),
+                column_options(*column, config, cache.as_ref()),
+            )
+        });
+        Self(DB::open_cf_descriptors(&options, path, families).unwrap())
+    }
+
+    fn cf(&self, column: Column) -> Result<&ColumnFamily> {
+        self.0
+            .cf_handle(column.name())
+            .ok_or_else(|| anyhow!("column family {} not found", column.name()))
+    }
+}
+
+// 元数据体积小、读多，使用布隆过滤器优化点查；内容体积大，加大写缓冲减少 flush 次数
+fn column_options(column: Column, config: &RocksDb, cache: Option<&Cache>) -> Options {
+    let mut options = Options::default();
+    let mut table = BlockBasedOptions::default();
+    if let Some(cache) = cache {
+        table.set_block_cache(cache);
+    }
+    if let Some(style) = config.compaction_style {
+        options.set_compaction_style(compaction_style(style));
+    }
+    if let Some(compression) = config.compression {
+        options.set_compression_type(compression_type(compression));
+    }
+    if let Some(size) = config.write_buffer_size {
+        options.set_write_buffer_size(size);
+    }
+
+    match column {
+        Column::Meta | Column::Names => {
+            table.set_bloom_filter(10.0, false);
+        }
+        Column::Content => {
+            if config.write_buffer_size.is_none() {
+                options.set_write_buffer_size(128 * 1024 * 1024);
+            }
+            options.set_target_file_size_base(256 * 1024 * 1024);
+            if config.enable_blob_files {
+                options.set_enable_blob_files(true);
+                options.set_min_blob_size(config.min_blob_size.unwrap_or(64 * 1024));
+                if let Some(size) = config.blob_file_size {
+                    options.set_blob_file_size(size);
+                }
+                if let Some(compression) = config.compression {
+                    options.set_blob_compression_type(compression_type(compression));
+                }
+            }
+        }
+        Column::Quarantine | Column::Uploads | Column::Legacy => {}
+    }
+    options.set_block_based_table_factory(&table);
+    options
+}
+
+fn compaction_style(style: CompactionStyle) -> DBCompactionStyle {
+    match style {
+        CompactionStyle::Level => DBCompactionStyle::Level,
+        CompactionStyle::Universal => DBCompactionStyle::Universal,
+    }
+}
+
+fn compression_type(compression: RocksDbCompression) -> DBCompressionType {
+    match compression {
+        RocksDbCompression::None => DBCompressionType::None,
+        RocksDbCompression::Snappy => DBCompressionType::Snappy,
+        RocksDbCompression::Zlib => DBCompressionType::Zlib,
+        RocksDbCompression::Bz2 => DBCompressionType::Bz2,
+        RocksDbCompression::Lz4 => DBCompressionType::Lz4,
+        RocksDbCompression::Lz4hc => DBCompressionType::Lz4hc,
+        RocksDbCompression::Zstd => DBCompressionType::Zstd,
     }
 }
 
 impl Storage for RocksDbStorage {
-    fn get(&self, key: &str) -> Result<Option<Vec<u8>>> {
-        let v = self.0.get(key)?;
-        match v {
-            Some(val) => Ok(Some(val)),
-            None => Err(anyhow!("not found")),
+    fn get(&self, column: Column, key: &str) -> Result<Option<Vec<u8>>> {
+        Ok(self.0.get_cf(self.cf(column)?, key)?)
+    }
+
+    fn exists(&self, column: Column, key: &str) -> bool {
+        match self.cf(column).ok() {
+            Some(cf) => self.0.get_pinned_cf(cf, key).ok().flatten().is_some(),
+            None => false,
         }
     }
 
-    fn exists(&self, key: &str) -> bool {
-        let v = self.get(key);
-        match v {
-            Err(_) => false,
-            _ => true,
+    fn put(&self, column: Column, key: &str, value: Vec<u8>) -> Result<()> {
+        self.0.put_cf(self.cf(column)?, key, value)?;
+        Ok(())
+    }
+
+    fn delete(&self, column: Column, key: &str) -> Result<()> {
+        self.0.delete_cf(self.cf(column)?, key)?;
+        Ok(())
+    }
+
+    fn scan(
+        &self,
+        column: Column,
+        prefix: &str,
+        after: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        let start = match after {
+            Some(after) if after > prefix => after,
+            _ => prefix,
+        };
+        let mode = IteratorMode::From(start.as_bytes(), Direction::Forward);
+
+        let mut items = Vec::new();
+        for item in self.0.iterator_cf(self.cf(column)?, mode) {
+            let (key, value) = item?;
+            let key = String::from_utf8(key.into_vec())?;
+            if !key.starts_with(prefix) || items.len() >= limit {
+                break;
+            }
+            if Some(key.as_str()) == after {
+                continue;
+            }
+            items.push((key, value.into_vec()));
         }
+        Ok(items)
     }
 
-    fn put(&self, key: &str, value: Vec<u8>) -> Result<()> {
-        if !self.exists(key) {
-            self.0.put(key, value.clone())?;
+    fn write(&self, batch: Vec<BatchOp>) -> Result<()> {
+        let mut write_batch = WriteBatch::default();
+        for op in batch {
+            match op {
+                BatchOp::Put(column, key, value) => {
+                    write_batch.put_cf(self.cf(column)?, key, value)
+                }
+                BatchOp::Delete(column, key) => write_batch.delete_cf(self.cf(column)?, key),
+            }
         }
+        self.0.write(write_batch)?;
         Ok(())
     }
-}
 
-pub static DB: OnceCell<RocksDbStorage> = OnceCell::const_new();
+    fn atomic_write(&self) -> bool {
+        true
+    }
+
+    fn capacity(&self) -> Result<Option<Capacity>> {
+        Ok(Some(volume_capacity(self.0.path())?))
+    }
 
-pub async fn rocksdb_conn() -> RocksDbStorage {
-    let db = RocksDbStorage::new(CONFIG.database.url.to_owned());
-    tracing::info!("database connected");
-    db
+    // 快照中的 SST 文件与数据目录在同一文件系统时为硬链接，几乎不占额外空间
+    fn checkpoint(&self, path: &Path) -> Result<()> {
+        Checkpoint::new(&self.0)?.create_checkpoint(path)?;
+        Ok(())
+    }
 }
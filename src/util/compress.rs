@@ -0,0 +1,29 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    #[default]
+    None,
+    Zstd,
+    Lz4,
+}
+
+pub fn compress(codec: Codec, level: i32, data: &[u8]) -> Result<Vec<u8>> {
+    let compressed = match codec {
+        Codec::None => data.to_vec(),
+        Codec::Zstd => zstd::bulk::compress(data, level)?,
+        Codec::Lz4 => lz4_flex::compress_prepend_size(data),
+    };
+    Ok(compressed)
+}
+
+pub fn decompress(codec: Codec, data: &[u8]) -> Result<Vec<u8>> {
+    let decompressed = match codec {
+        Codec::None => data.to_vec(),
+        Codec::Zstd => zstd::stream::decode_all(data)?,
+        Codec::Lz4 => lz4_flex::decompress_size_prepended(data)?,
+    };
+    Ok(decompressed)
+}
@@ -1,13 +1,101 @@
+use anyhow::{anyhow, Result};
 use data_encoding::HEXUPPER;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
 use ring::digest::{Context, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
 
-pub fn sha256_digest(buffer: &Vec<u8>) -> String {
+pub const KEY_LEN: usize = 32;
+
+pub fn sha256_digest(buffer: &[u8]) -> String {
     let mut context = Context::new(&SHA256);
-    if buffer.len() == 0 {
+    if buffer.is_empty() {
         return String::from("");
     }
-    context.update(&buffer);
+    context.update(buffer);
     let digest = context.finish();
     let signature = HEXUPPER.encode(digest.as_ref());
     signature
 }
+
+// 分段计算 SHA-256，结果与 sha256_digest 一致
+pub struct Sha256 {
+    context: Context,
+    empty: bool,
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Sha256 {
+            context: Context::new(&SHA256),
+            empty: true,
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.empty &= data.is_empty();
+        self.context.update(data);
+    }
+
+    pub fn finish(self) -> String {
+        if self.empty {
+            return String::from("");
+        }
+        HEXUPPER.encode(self.context.finish().as_ref())
+    }
+}
+
+impl Default for Sha256 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn random_key() -> Result<[u8; KEY_LEN]> {
+    let mut key = [0u8; KEY_LEN];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| anyhow!("generate key failed"))?;
+    Ok(key)
+}
+
+// AES-256-GCM 加密，输出为 nonce + 密文 + tag，nonce 随机生成
+pub fn encrypt(key: &[u8], aad: &[u8], mut data: Vec<u8>) -> Result<Vec<u8>> {
+    let key =
+        LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key).map_err(|_| anyhow!("invalid key"))?);
+    let mut nonce = [0u8; NONCE_LEN];
+    SystemRandom::new()
+        .fill(&mut nonce)
+        .map_err(|_| anyhow!("generate nonce failed"))?;
+    key.seal_in_place_append_tag(
+        Nonce::assume_unique_for_key(nonce),
+        Aad::from(aad),
+        &mut data,
+    )
+    .map_err(|_| anyhow!("encrypt failed"))?;
+
+    let mut sealed = Vec::with_capacity(NONCE_LEN + data.len());
+    sealed.extend_from_slice(&nonce);
+    sealed.extend(data);
+    Ok(sealed)
+}
+
+pub fn decrypt(key: &[u8], aad: &[u8], mut data: Vec<u8>) -> Result<Vec<u8>> {
+    if data.len() < NONCE_LEN {
+        return Err(anyhow!("decrypt failed, data too short"));
+    }
+    let key =
+        LessSafeKey::new(UnboundKey::new(&AES_256_GCM, key).map_err(|_| anyhow!("invalid key"))?);
+    let mut nonce = [0u8; NONCE_LEN];
+    nonce.copy_from_slice(&data[..NONCE_LEN]);
+    let mut sealed = data.split_off(NONCE_LEN);
+    let len = key
+        .open_in_place(
+            Nonce::assume_unique_for_key(nonce),
+            Aad::from(aad),
+            &mut sealed,
+        )
+        .map_err(|_| anyhow!("decrypt failed"))?
+        .len();
+    sealed.truncate(len);
+    Ok(sealed)
+}
@@ -0,0 +1,90 @@
+use std::collections::{BTreeMap, HashMap};
+
+// 按字节数限制容量的 LRU 缓存，超出容量时淘汰最久未访问的条目
+pub struct LruCache<V> {
+    // key -> (值, 字节数, 最近访问序号)
+    entries: HashMap<String, (V, usize, u64)>,
+    // 最近访问序号 -> key，序号最小的最久未访问
+    order: BTreeMap<u64, String>,
+    tick: u64,
+    used: usize,
+    limit: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl<V: Clone> LruCache<V> {
+    pub fn new(limit: usize) -> Self {
+        LruCache {
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+            tick: 0,
+            used: 0,
+            limit,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<V> {
+        match self.entries.get_mut(key) {
+            Some((value, _, tick)) => {
+                self.order.remove(tick);
+                self.tick += 1;
+                *tick = self.tick;
+                self.order.insert(self.tick, key.to_string());
+                self.hits += 1;
+                Some(value.clone())
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn insert(&mut self, key: &str, value: V, size: usize) {
+        self.remove(key);
+        if size > self.limit {
+            return;
+        }
+        while self.used + size > self.limit {
+            match self.order.pop_first() {
+                Some((_, oldest)) => {
+                    if let Some((_, size, _)) = self.entries.remove(&oldest) {
+                        self.used -= size;
+                    }
+                }
+                None => break,
+            }
+        }
+        self.tick += 1;
+        self.order.insert(self.tick, key.to_string());
+        self.entries
+            .insert(key.to_string(), (value, size, self.tick));
+        self.used += size;
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        if let Some((_, size, tick)) = self.entries.remove(key) {
+            self.order.remove(&tick);
+            self.used -= size;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn used(&self) -> usize {
+        self.used
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
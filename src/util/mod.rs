@@ -1 +1,4 @@
+pub mod compress;
 pub mod crypto;
+pub mod lru;
+pub mod range;
@@ -0,0 +1,173 @@
+// 区间数超过该值时忽略 Range 请求头，避免大量小区间放大响应
+const MAX_RANGES: usize = 16;
+
+// 请求的字节区间，end 包含在内
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    pub fn content_range(&self, size: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, size)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Ranges {
+    // 没有 Range 请求头或无法解析，返回完整内容
+    Full,
+    Partial(Vec<ByteRange>),
+    // 所有区间都超出文件大小
+    Unsatisfiable,
+}
+
+// 解析 Range 请求头，区间按请求的顺序返回，超出文件大小的部分被截断
+pub fn parse(header: &str, size: u64) -> Ranges {
+    let specs = match header.trim().split_once('=') {
+        Some((unit, specs)) if unit.trim().eq_ignore_ascii_case("bytes") => specs,
+        _ => return Ranges::Full,
+    };
+
+    let mut ranges = Vec::new();
+    let mut count = 0;
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        count += 1;
+        if count > MAX_RANGES {
+            return Ranges::Full;
+        }
+        let (first, last) = match spec.split_once('-') {
+            Some((first, last)) => (first.trim(), last.trim()),
+            None => return Ranges::Full,
+        };
+        if first.is_empty() {
+            // 后缀区间，表示最后 n 个字节
+            let suffix = match number(last) {
+                Some(suffix) => suffix,
+                None => return Ranges::Full,
+            };
+            if suffix > 0 && size > 0 {
+                ranges.push(ByteRange {
+                    start: size.saturating_sub(suffix),
+                    end: size - 1,
+                });
+            }
+            continue;
+        }
+
+        let start = match number(first) {
+            Some(start) => start,
+            None => return Ranges::Full,
+        };
+        let end = match last {
+            "" => u64::MAX,
+            last => match number(last) {
+                Some(end) if end >= start => end,
+                _ => return Ranges::Full,
+            },
+        };
+        if start < size {
+            ranges.push(ByteRange {
+                start,
+                end: end.min(size - 1),
+            });
+        }
+    }
+
+    match (count, ranges.is_empty()) {
+        (0, _) => Ranges::Full,
+        (_, true) => Ranges::Unsatisfiable,
+        _ => Ranges::Partial(ranges),
+    }
+}
+
+fn number(s: &str) -> Option<u64> {
+    if s.is_empty() || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn partial(ranges: &[(u64, u64)]) -> Ranges {
+        Ranges::Partial(
+            ranges
+                .iter()
+                .map(|&(start, end)| ByteRange { start, end })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn single_range() {
+        assert_eq!(parse("bytes=0-99", 1000), partial(&[(0, 99)]));
+        assert_eq!(parse("bytes=900-", 1000), partial(&[(900, 999)]));
+        assert_eq!(parse("bytes=900-5000", 1000), partial(&[(900, 999)]));
+    }
+
+    #[test]
+    fn suffix_range() {
+        assert_eq!(parse("bytes=-100", 1000), partial(&[(900, 999)]));
+        assert_eq!(parse("bytes=-5000", 1000), partial(&[(0, 999)]));
+        assert_eq!(parse("bytes=-0", 1000), Ranges::Unsatisfiable);
+    }
+
+    #[test]
+    fn empty_file() {
+        assert_eq!(parse("bytes=0-", 0), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=-100", 0), Ranges::Unsatisfiable);
+    }
+
+    #[test]
+    fn start_after_end() {
+        assert_eq!(parse("bytes=100-50", 1000), Ranges::Full);
+        assert_eq!(parse("bytes=0-10, 100-50", 1000), Ranges::Full);
+    }
+
+    #[test]
+    fn invalid_header() {
+        assert_eq!(parse("items=0-10", 1000), Ranges::Full);
+        assert_eq!(parse("bytes=", 1000), Ranges::Full);
+        assert_eq!(parse("bytes=a-b", 1000), Ranges::Full);
+        assert_eq!(parse("bytes=+1-2", 1000), Ranges::Full);
+    }
+
+    #[test]
+    fn multiple_ranges() {
+        assert_eq!(
+            parse("bytes=500-599, 0-99, -10", 1000),
+            partial(&[(500, 599), (0, 99), (990, 999)])
+        );
+        // 超出文件大小的区间被丢弃
+        assert_eq!(parse("bytes=0-9, 2000-2999", 1000), partial(&[(0, 9)]));
+    }
+
+    #[test]
+    fn range_cap() {
+        let specs: Vec<String> = (0..MAX_RANGES as u64)
+            .map(|i| format!("{}-{}", i * 10, i * 10 + 4))
+            .collect();
+        match parse(&format!("bytes={}", specs.join(",")), 1000) {
+            Ranges::Partial(ranges) => assert_eq!(ranges.len(), MAX_RANGES),
+            ranges => panic!("unexpected {:?}", ranges),
+        }
+
+        let specs: Vec<String> = (0..=MAX_RANGES as u64)
+            .map(|i| format!("{}-{}", i * 10, i * 10 + 4))
+            .collect();
+        assert_eq!(
+            parse(&format!("bytes={}", specs.join(",")), 1000),
+            Ranges::Full
+        );
+    }
+
+    #[test]
+    fn all_unsatisfiable() {
+        assert_eq!(parse("bytes=1000-", 1000), Ranges::Unsatisfiable);
+        assert_eq!(parse("bytes=1000-1999, 5000-", 1000), Ranges::Unsatisfiable);
+    }
+}